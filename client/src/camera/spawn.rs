@@ -1,6 +1,7 @@
 use bevy::prelude::*;
 use bevy_atmosphere::prelude::AtmosphereCamera;
 
+use crate::constants::DEFAULT_SKY_COLOR;
 use crate::GameState;
 
 #[derive(Component)]
@@ -48,6 +49,15 @@ pub fn spawn_camera(mut commands: Commands) {
             .looking_at(Vec3::new(0.0, 0.5, 0.0), Vec3::Y),
         CameraController::default(),
         AtmosphereCamera::default(),
+        // Faded to the sky color of the biome the camera is in
+        DistanceFog {
+            color: Color::srgb(
+                DEFAULT_SKY_COLOR[0],
+                DEFAULT_SKY_COLOR[1],
+                DEFAULT_SKY_COLOR[2],
+            ),
+            ..default()
+        },
         StateScoped(GameState::Game),
     ));
 }
@@ -19,7 +19,11 @@ pub const SAVE_PATH: &str = "saves/";
 pub const SERVER_LIST_SAVE_NAME: &str = "servers.ron";
 pub const BINDS_PATH: &str = "keybindings.ron";
 
-pub const GRASS_COLOR: [f32; 4] = [0.1, 1.0, 0.3, 1.0];
+/// Tints used where the biome is unknown
+pub const DEFAULT_GRASS_COLOR: [f32; 3] = [0.1, 1.0, 0.3];
+pub const DEFAULT_FOLIAGE_COLOR: [f32; 3] = [0.1, 1.0, 0.3];
+pub const DEFAULT_WATER_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+pub const DEFAULT_SKY_COLOR: [f32; 3] = [0.47, 0.65, 1.0];
 
 pub const TEXTURE_PATH_BASE: &str = "graphics/base_textures/";
 pub const TEXTURE_PATH_CUSTOM: &str = "graphics/custom_textures/";
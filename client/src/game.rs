@@ -8,7 +8,9 @@ use crate::ui::menus::{setup_server_connect_loading_screen, update_server_connec
 use bevy::prelude::*;
 use bevy_atmosphere::prelude::*;
 use shared::messages::mob::MobUpdateEvent;
-use shared::messages::{ItemStackUpdateEvent, PlayerSpawnEvent, PlayerUpdateEvent};
+use shared::messages::{
+    ItemStackUpdateEvent, PlayerDespawnEvent, PlayerSpawnEvent, PlayerUpdateEvent,
+};
 use shared::players::{Inventory, ViewMode};
 use shared::TICKS_PER_SECOND;
 use time::time_update_system;
@@ -25,6 +27,7 @@ use bevy::pbr::wireframe::{WireframeConfig, WireframePlugin};
 
 use crate::ui::hud::debug::targeted_block::block_text_update_system;
 use crate::world::celestial::setup_main_lighting;
+use crate::world::rendering::tints::update_sky_color_system;
 
 use crate::ui::hud::debug::*;
 use crate::ui::hud::hotbar::*;
@@ -41,8 +44,9 @@ use shared::world::{BlockId, ItemId, WorldSeed};
 use crate::network::{
     establish_authenticated_connection_to_server, init_server_connection,
     launch_local_server_system, network_failure_handler, poll_network_messages,
-    terminate_server_connection, upload_player_inputs_system, CurrentPlayerProfile, TargetServer,
-    TargetServerState, UnacknowledgedInputs,
+    send_heartbeat_system, send_view_distance_system, terminate_server_connection,
+    upload_player_inputs_system, CurrentPlayerProfile, TargetServer, TargetServerState,
+    UnacknowledgedInputs,
 };
 
 use crate::GameState;
@@ -98,6 +102,7 @@ pub fn game_plugin(app: &mut App) {
         .insert_resource(Time::<Fixed>::from_hz(TICKS_PER_SECOND as f64))
         .add_event::<WorldRenderRequestUpdateEvent>()
         .add_event::<PlayerSpawnEvent>()
+        .add_event::<PlayerDespawnEvent>()
         .add_event::<PlayerUpdateEvent>()
         .add_event::<MobUpdateEvent>()
         .add_event::<ItemStackUpdateEvent>()
@@ -152,7 +157,7 @@ pub fn game_plugin(app: &mut App) {
         .add_systems(
             Update,
             (
-                render_distance_update_system,
+                (render_distance_update_system, send_view_distance_system).chain(),
                 first_and_third_person_view_system,
                 toggle_chunk_debug_mode_system,
                 toggle_raycast_debug_mode_system,
@@ -175,6 +180,7 @@ pub fn game_plugin(app: &mut App) {
                 toggle_wireframe_system,
                 handle_mouse_system,
                 update_celestial_bodies,
+                update_sky_color_system,
             )
                 .run_if(in_state(GameState::Game)),
         )
@@ -198,6 +204,7 @@ pub fn game_plugin(app: &mut App) {
             (
                 network_failure_handler,
                 spawn_players_system,
+                despawn_players_system,
                 update_players_system,
                 spawn_mobs_system,
                 player_labels_system,
@@ -212,6 +219,11 @@ pub fn game_plugin(app: &mut App) {
             FixedPreUpdate,
             poll_network_messages.run_if(in_state(GameState::Game)),
         )
+        .add_systems(
+            Update,
+            send_heartbeat_system
+                .run_if(in_state(GameState::PreGameLoading).or(in_state(GameState::Game))),
+        )
         .add_systems(
             FixedUpdate,
             (upload_player_inputs_system).run_if(in_state(GameState::Game)),
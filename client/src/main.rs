@@ -24,8 +24,10 @@ use constants::{TEXTURE_PATH_BASE, TEXTURE_PATH_CUSTOM};
 use input::{data::GameAction, keyboard::get_bindings};
 use menus::solo::SelectedWorld;
 use serde::{Deserialize, Serialize};
-use shared::{get_game_folder_paths, SpecialFlag};
+use shared::world::BiomeRegistry;
+use shared::{get_game_folder_paths, AccountToken, SpecialFlag};
 use std::collections::BTreeMap;
+use std::path::PathBuf;
 use ui::{
     hud::debug::inspector::inspector_ui,
     menus::{self, splash},
@@ -53,6 +55,12 @@ struct Args {
 
     #[arg(short, long, help = "Player name to use for the game")]
     player_name: Option<String>,
+
+    #[arg(
+        long,
+        help = "Connect token issued by a server started with --secure, used to join that server"
+    )]
+    connect_token: Option<PathBuf>,
 }
 
 #[derive(Component)]
@@ -121,6 +129,26 @@ fn main() {
 
     let special_flag = SpecialFlag { special_flag };
 
+    let account_token = args.connect_token.map(|path| {
+        match std::fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                ron::de::from_str::<AccountToken>(&contents).map_err(|err| err.to_string())
+            }) {
+            Ok(token) => {
+                println!(
+                    "Using the connect token of {} for {}",
+                    token.username, token.server_address
+                );
+                token
+            }
+            Err(err) => {
+                eprintln!("Could not read connect token {} : {}", path.display(), err);
+                std::process::exit(1);
+            }
+        }
+    });
+
     let mut app = App::new();
     app.add_plugins(
         DefaultPlugins
@@ -158,10 +186,15 @@ fn main() {
 
     app.add_event::<LoadWorldEvent>();
     network::add_base_netcode(&mut app);
+    if let Some(account_token) = account_token {
+        app.insert_resource(account_token);
+    }
     app.insert_resource(get_bindings(&game_folder_paths))
         .insert_resource(SelectedWorld::default())
         // Declare the game state, whose starting value is determined by the `Default` trait
         .insert_resource(ClientWorldMap { ..default() })
+        // Replaced by the biomes of the server once connected
+        .insert_resource(BiomeRegistry::builtin())
         .insert_resource(TexturePath {
             path: texture_path.to_string(),
         })
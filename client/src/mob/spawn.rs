@@ -1,5 +1,6 @@
 use bevy::prelude::*;
 use shared::messages::mob::MobUpdateEvent;
+use shared::CHUNK_SIZE;
 
 use crate::{mob::setup_fox, player::CurrentPlayerMarker, world::RenderDistance};
 
@@ -30,16 +31,19 @@ pub fn spawn_mobs_system(
         }
 
         if event.mob.kind == shared::world::MobKind::Fox
-            && event.mob.position.distance(player_pos) < render_distance.distance as f32 * 5.0
+            && event.mob.position.distance(player_pos)
+                < (render_distance.distance as i32 * CHUNK_SIZE) as f32
         {
             info!("Spawning fox at {:?}", position);
             setup_fox(id, position, &mut commands, &asset_server, &mut graphs);
         }
     }
 
-    // Despawn entities which are too far away
+    // Despawn entities which are too far away, the server stops sending them past the view distance
     for (entity, _, transform) in mobs.iter() {
-        if transform.translation.distance(player_pos) > render_distance.distance as f32 * 5.0 {
+        if transform.translation.distance(player_pos)
+            > (render_distance.distance as i32 * CHUNK_SIZE) as f32
+        {
             commands.entity(entity).despawn();
         }
     }
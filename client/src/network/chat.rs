@@ -7,7 +7,7 @@ pub struct CachedChatConversation {
     pub data: Option<ChatConversation>,
 }
 
-pub fn _update_cached_chat_state(
+pub fn update_cached_chat_state(
     chat_state: &mut ResMut<CachedChatConversation>,
     new_state: ChatConversation,
 ) {
@@ -0,0 +1,25 @@
+use bevy::prelude::*;
+use bevy_renet::renet::RenetClient;
+use shared::messages::ClientToServerMessage;
+use shared::HEARTBEAT_INTERVAL_SECS;
+use std::time::{Duration, Instant};
+
+use crate::network::SendGameMessageExtension;
+
+/// Tells the server the client is still alive, even when the player does nothing
+pub fn send_heartbeat_system(
+    mut client: ResMut<RenetClient>,
+    mut last_sent: Local<Option<Instant>>,
+) {
+    if !client.is_connected() {
+        return;
+    }
+
+    let interval = Duration::from_secs(HEARTBEAT_INTERVAL_SECS);
+    if last_sent.is_some_and(|last_sent| last_sent.elapsed() < interval) {
+        return;
+    }
+
+    client.send_game_message(ClientToServerMessage::Heartbeat);
+    *last_sent = Some(Instant::now());
+}
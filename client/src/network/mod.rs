@@ -2,13 +2,17 @@ pub mod buffered_client;
 mod chat;
 mod cleanup;
 pub mod extensions;
+mod heartbeat;
 mod inputs;
 pub mod save;
 mod setup;
+mod view_distance;
 mod world;
 
 pub use chat::*;
 pub use cleanup::*;
 pub use extensions::SendGameMessageExtension;
+pub use heartbeat::*;
 pub use inputs::*;
 pub use setup::*;
+pub use view_distance::*;
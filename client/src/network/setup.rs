@@ -1,28 +1,33 @@
 use bevy::prelude::*;
 use bevy_renet::netcode::{
-    ClientAuthentication, NetcodeClientPlugin, NetcodeClientTransport, NetcodeTransportError,
+    ClientAuthentication, ConnectToken, NetcodeClientPlugin, NetcodeClientTransport,
+    NetcodeTransportError,
 };
 use bevy_renet::{renet::RenetClient, RenetClientPlugin};
 use rand::Rng;
 use shared::messages::mob::MobUpdateEvent;
-use shared::{get_shared_renet_config, GameServerConfig, STC_AUTH_CHANNEL};
+use shared::{
+    get_shared_renet_config, AccountToken, GameServerConfig, DEFAULT_AUTH_TIMEOUT_SECS,
+    DEFAULT_AUTOSAVE_INTERVAL_SECS, DEFAULT_BACKUP_COUNT, DEFAULT_BACKUP_INTERVAL_SECS,
+    DEFAULT_BACKUP_MAX_AGE_SECS, DEFAULT_HEARTBEAT_TIMEOUT_SECS, DEFAULT_MAX_PLAYERS,
+    DEFAULT_MAX_VIEW_DISTANCE, STC_AUTH_CHANNEL,
+};
 
 use crate::menus::solo::SelectedWorld;
 use crate::network::world::update_world_from_network;
 use crate::network::CachedChatConversation;
 use crate::world::time::ClientTime;
-use crate::world::WorldRenderRequestUpdateEvent;
+use crate::world::{RenderDistance, WorldRenderRequestUpdateEvent};
 use crate::PlayerNameSupplied;
 use shared::messages::{
-    AuthRegisterRequest, ItemStackUpdateEvent, PlayerId, PlayerSpawnEvent, PlayerUpdateEvent,
-    ServerToClientMessage,
+    AuthRegisterRequest, GameVersion, ItemStackUpdateEvent, PlayerDespawnEvent, PlayerId,
+    PlayerSpawnEvent, PlayerUpdateEvent, ServerToClientMessage,
 };
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::{net::UdpSocket, thread, time::SystemTime};
 
 use crate::world::ClientWorldMap;
+use shared::world::{BiomeRegistry, WorldGeneratorConfig};
 use shared::GameFolderPaths;
 
 use super::SendGameMessageExtension;
@@ -33,6 +38,8 @@ pub enum TargetServerState {
     Establishing,
     ConnectionEstablished,
     FullyReady, // player has spawned
+    /// The server refused the connection, for the given reason
+    Rejected(String),
 }
 
 #[derive(Resource, Clone)]
@@ -52,18 +59,20 @@ impl CurrentPlayerProfile {
     }
 }
 
-fn hash_string_to_u64(input: &str) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    input.hash(&mut hasher);
-    hasher.finish()
-}
-
 impl FromWorld for CurrentPlayerProfile {
     fn from_world(world: &mut World) -> Self {
+        // The server only accepts the account the token was issued for
+        if let Some(token) = world.get_resource::<AccountToken>() {
+            return Self {
+                id: token.account_id,
+                name: token.username.clone(),
+            };
+        }
+
         let player_name = world.get_resource::<PlayerNameSupplied>();
         match player_name {
             Some(player_name) => Self {
-                id: hash_string_to_u64(&player_name.name),
+                id: rand::random(),
                 name: player_name.name.clone(),
             },
             None => CurrentPlayerProfile::new(),
@@ -124,6 +133,19 @@ pub fn launch_local_server_system(
                 GameServerConfig {
                     world_name: world_name_clone,
                     is_solo: true,
+                    autosave_interval_secs: DEFAULT_AUTOSAVE_INTERVAL_SECS,
+                    backup_interval_secs: DEFAULT_BACKUP_INTERVAL_SECS,
+                    backup_count: DEFAULT_BACKUP_COUNT,
+                    backup_max_age_secs: DEFAULT_BACKUP_MAX_AGE_SECS,
+                    world_generator: WorldGeneratorConfig::default(),
+                    pregenerate: None,
+                    max_view_distance: DEFAULT_MAX_VIEW_DISTANCE,
+                    secure: false,
+                    public_address: None,
+                    max_players: DEFAULT_MAX_PLAYERS,
+                    whitelist: false,
+                    heartbeat_timeout_secs: DEFAULT_HEARTBEAT_TIMEOUT_SECS,
+                    auth_timeout_secs: DEFAULT_AUTH_TIMEOUT_SECS,
                 },
                 cloned_paths,
             );
@@ -137,21 +159,27 @@ pub fn launch_local_server_system(
 
 pub fn poll_network_messages(
     mut client: ResMut<RenetClient>,
-    // mut chat_state: ResMut<CachedChatConversation>,
+    mut chat_state: ResMut<CachedChatConversation>,
     // client_time: ResMut<ClientTime>,
     mut world: ResMut<ClientWorldMap>,
     mut ev_render: EventWriter<WorldRenderRequestUpdateEvent>,
     mut ev_player_spawn: EventWriter<PlayerSpawnEvent>,
+    mut ev_player_despawn: EventWriter<PlayerDespawnEvent>,
     mut ev_mob_update: EventWriter<MobUpdateEvent>,
     mut ev_item_stacks_update: EventWriter<ItemStackUpdateEvent>,
     mut ev_player_update: EventWriter<PlayerUpdateEvent>,
+    mut render_distance: ResMut<RenderDistance>,
+    mut commands: Commands,
 ) {
-    // poll_reliable_ordered_messages(&mut client, &mut chat_state);
     update_world_from_network(
         &mut client,
         &mut world,
+        &mut render_distance,
+        &mut chat_state,
+        &mut commands,
         &mut ev_render,
         &mut ev_player_spawn,
+        &mut ev_player_despawn,
         &mut ev_mob_update,
         &mut ev_item_stacks_update,
         &mut ev_player_update,
@@ -160,11 +188,47 @@ pub fn poll_network_messages(
 
 pub fn init_server_connection(
     mut commands: Commands,
-    target: Res<TargetServer>,
-    current_player_id: Res<CurrentPlayerProfile>,
+    mut target: ResMut<TargetServer>,
+    mut current_profile: ResMut<CurrentPlayerProfile>,
+    account_token: Option<Res<AccountToken>>,
 ) {
     let addr = target.address.unwrap();
-    let id = current_player_id.into_inner().id;
+
+    let authentication = match account_token {
+        Some(token) if token.server_address != addr => {
+            error!(
+                "Connect token issued for {}, not for {}",
+                token.server_address, addr
+            );
+            target.state = TargetServerState::Rejected(format!(
+                "Connect token issued for another address : {}",
+                token.server_address
+            ));
+            return;
+        }
+        Some(token) => match ConnectToken::read(&mut token.connect_token.as_slice()) {
+            Ok(connect_token) => ClientAuthentication::Secure { connect_token },
+            Err(err) => {
+                error!("Invalid connect token : {}", err);
+                target.state =
+                    TargetServerState::Rejected(format!("Invalid connect token : {err}"));
+                return;
+            }
+        },
+        None => {
+            // Without a token the id only identifies the connection, the server finds saves
+            // by name. A new one is picked every time, so that two players with the same name
+            // reach the server and the second one is told the name is taken
+            current_profile.id = rand::random();
+            ClientAuthentication::Unsecure {
+                server_addr: addr,
+                client_id: current_profile.id,
+                user_data: None,
+                protocol_id: shared::PROTOCOL_ID,
+            }
+        }
+    };
+
     commands.queue(move |world: &mut World| {
         world.remove_resource::<RenetClient>();
         world.remove_resource::<NetcodeClientTransport>();
@@ -173,16 +237,13 @@ pub fn init_server_connection(
         let client = RenetClient::new(get_shared_renet_config());
         world.insert_resource(client);
 
-        let authentication = ClientAuthentication::Unsecure {
-            server_addr: addr,
-            client_id: id,
-            user_data: None,
-            protocol_id: shared::PROTOCOL_ID,
-        };
-
         info!(
-            "Attempting to connect to: {} with data {:?}",
-            addr, authentication
+            "Attempting to connect to: {} ({})",
+            addr,
+            match authentication {
+                ClientAuthentication::Secure { .. } => "with a connect token",
+                ClientAuthentication::Unsecure { .. } => "unsecure",
+            }
         );
 
         let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
@@ -206,6 +267,7 @@ pub fn network_failure_handler(mut renet_error: EventReader<NetcodeTransportErro
 }
 
 pub fn establish_authenticated_connection_to_server(
+    mut commands: Commands,
     mut client: ResMut<RenetClient>,
     mut target: ResMut<TargetServer>,
     current_profile: Res<CurrentPlayerProfile>,
@@ -228,6 +290,7 @@ pub fn establish_authenticated_connection_to_server(
         let username = target.username.as_ref().unwrap();
 
         let auth_msg = AuthRegisterRequest {
+            version: GameVersion::current(),
             username: username.clone(),
         };
         info!("Sending auth request: {:?}", auth_msg);
@@ -235,9 +298,21 @@ pub fn establish_authenticated_connection_to_server(
         target.state = TargetServerState::Establishing;
     }
 
-    while let Some(Ok(message)) = client.receive_game_message_by_channel(STC_AUTH_CHANNEL) {
+    while let Some(message) = client.receive_game_message_by_channel(STC_AUTH_CHANNEL) {
+        let Ok(message) = message else {
+            // Clients of any version can read the response, unless the server is too old
+            target.state = TargetServerState::Rejected(
+                "Unreadable response from the server, it may run an incompatible version".into(),
+            );
+            continue;
+        };
+
         match message {
-            ServerToClientMessage::AuthRegisterResponse(message) => {
+            ServerToClientMessage::AuthRegisterResponse(Err(err)) => {
+                error!("Connection refused by the server : {}", err);
+                target.state = TargetServerState::Rejected(err.to_string());
+            }
+            ServerToClientMessage::AuthRegisterResponse(Ok(message)) => {
                 target.username = Some(message.username);
                 target.session_token = Some(message.session_token);
                 target.state = TargetServerState::ConnectionEstablished;
@@ -247,6 +322,7 @@ pub fn establish_authenticated_connection_to_server(
                 for player in message.players {
                     ev_spawn.write(player);
                 }
+                commands.insert_resource(BiomeRegistry::from_definitions(message.biomes));
                 info!("Connected! {:?}", target);
             }
             _ => {
@@ -254,4 +330,14 @@ pub fn establish_authenticated_connection_to_server(
             }
         }
     }
+
+    // Dropped by the server without being told why, e.g. when it is already full of connections
+    if target.state == TargetServerState::Establishing && client.is_disconnected() {
+        let reason = client
+            .disconnect_reason()
+            .map(|reason| reason.to_string())
+            .unwrap_or_else(|| "Disconnected from the server".into());
+        error!("Connection to the server lost : {}", reason);
+        target.state = TargetServerState::Rejected(reason);
+    }
 }
@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+use bevy_renet::renet::RenetClient;
+use shared::messages::ClientToServerMessage;
+
+use crate::network::SendGameMessageExtension;
+use crate::world::RenderDistance;
+
+/// Asks the server for the render distance of the client whenever it changes.\
+/// The server answers with the view distance it granted, which replaces the requested one
+pub fn send_view_distance_system(
+    render_distance: Res<RenderDistance>,
+    mut client: ResMut<RenetClient>,
+    mut last_requested: Local<u32>,
+) {
+    // New connection, the server does not know the render distance yet
+    if client.is_added() {
+        *last_requested = 0;
+    }
+
+    if render_distance.distance == 0 || render_distance.distance == *last_requested {
+        return;
+    }
+
+    debug!("Requesting a view distance of {}", render_distance.distance);
+    client.send_game_message(ClientToServerMessage::SetViewDistance(
+        render_distance.distance,
+    ));
+    *last_requested = render_distance.distance;
+}
@@ -4,12 +4,14 @@ use crate::world::ClientChunk;
 use bevy::prelude::*;
 use bevy_renet::renet::RenetClient;
 use shared::messages::{
-    mob::MobUpdateEvent, ItemStackUpdateEvent, PlayerSpawnEvent, PlayerUpdateEvent,
-    ServerToClientMessage,
+    mob::MobUpdateEvent, ItemStackUpdateEvent, PlayerDespawnEvent, PlayerSpawnEvent,
+    PlayerUpdateEvent, ServerToClientMessage,
 };
+use shared::world::{global_block_to_chunk_pos, WorldMap};
 use shared::STC_AUTH_CHANNEL;
 
-use crate::world::ClientWorldMap;
+use crate::network::{update_cached_chat_state, CachedChatConversation};
+use crate::world::{ClientWorldMap, RenderDistance};
 
 use crate::world::WorldRenderRequestUpdateEvent;
 
@@ -18,8 +20,12 @@ use super::SendGameMessageExtension;
 pub fn update_world_from_network(
     client: &mut ResMut<RenetClient>,
     world: &mut ResMut<ClientWorldMap>,
+    render_distance: &mut ResMut<RenderDistance>,
+    chat_state: &mut ResMut<CachedChatConversation>,
+    commands: &mut Commands,
     ev_render: &mut EventWriter<WorldRenderRequestUpdateEvent>,
     ev_player_spawn: &mut EventWriter<PlayerSpawnEvent>,
+    ev_player_despawn: &mut EventWriter<PlayerDespawnEvent>,
     ev_mob_update: &mut EventWriter<MobUpdateEvent>,
     ev_item_stacks_update: &mut EventWriter<ItemStackUpdateEvent>,
     ev_player_update: &mut EventWriter<PlayerUpdateEvent>,
@@ -37,7 +43,8 @@ pub fn update_world_from_network(
 
                 for (pos, chunk) in world_update.new_map {
                     let chunk = ClientChunk {
-                        map: chunk.map,
+                        blocks: chunk.blocks,
+                        biomes: chunk.biomes,
                         entity: {
                             if let Some(c) = world.map.get(&pos) {
                                 c.entity
@@ -62,10 +69,53 @@ pub fn update_world_from_network(
                 // get current time
                 // client_time.0 = world_update.time;
             }
+            ServerToClientMessage::BlockUpdate(block_update) => {
+                debug!(
+                    "Received block update, {} blocks changed",
+                    block_update.blocks.len()
+                );
+
+                for (block_pos, block) in block_update.blocks {
+                    match block {
+                        Some(block) => world.set_block(&block_pos, block),
+                        None => {
+                            world.remove_block_by_coordinates(&block_pos);
+                        }
+                    }
+                    ev_render.write(WorldRenderRequestUpdateEvent::ChunkToReload(
+                        global_block_to_chunk_pos(&block_pos),
+                    ));
+                }
+            }
+            ServerToClientMessage::ChunkUnload(chunk_unload) => {
+                debug!(
+                    "Received chunk unload, {} chunks unloaded",
+                    chunk_unload.chunks.len()
+                );
+
+                for chunk_pos in chunk_unload.chunks {
+                    if let Some(entity) = world.map.remove(&chunk_pos).and_then(|c| c.entity) {
+                        commands.entity(entity).despawn();
+                    }
+                }
+            }
+            ServerToClientMessage::ViewDistance(view_distance) => {
+                if view_distance != render_distance.distance {
+                    info!(
+                        "Render distance of {} capped to {} by the server",
+                        render_distance.distance, view_distance
+                    );
+                    render_distance.distance = view_distance;
+                }
+            }
             ServerToClientMessage::PlayerSpawn(spawn_event) => {
                 info!("Received SINGLE spawn event {:?}", spawn_event);
                 ev_player_spawn.write(spawn_event);
             }
+            ServerToClientMessage::PlayerDespawn(despawn_event) => {
+                info!("Received despawn event {:?}", despawn_event);
+                ev_player_despawn.write(despawn_event);
+            }
             ServerToClientMessage::MobUpdate(update_event) => {
                 // info!("Received mob update event {:?}", update_event);
                 ev_mob_update.write(update_event);
@@ -74,7 +124,9 @@ pub fn update_world_from_network(
                 ev_player_update.write(update);
             }
             ServerToClientMessage::AuthRegisterResponse(_) => {}
-            ServerToClientMessage::ChatConversation(_) => {}
+            ServerToClientMessage::ChatConversation(conversation) => {
+                update_cached_chat_state(chat_state, conversation);
+            }
         }
     }
 }
@@ -8,8 +8,10 @@ use crate::{
 use bevy::color::palettes::css::ORANGE;
 use bevy::prelude::*;
 use shared::{
-    messages::{PlayerSpawnEvent, PlayerUpdateEvent},
-    players::{simulation::simulate_player_actions, blocks::CallerType, Inventory, Player},
+    messages::{PlayerDespawnEvent, PlayerSpawnEvent, PlayerUpdateEvent},
+    players::{
+        blocks::CallerType, simulation::simulate_player_actions, Inventory, Player, ViewMode,
+    },
 };
 
 #[derive(Component)]
@@ -27,6 +29,7 @@ pub fn spawn_players_system(
     players: Query<&Player>,
     assets: Res<AssetServer>,
     mut camera_query: Query<(&mut Transform, &mut CameraController), With<Camera>>,
+    (mut inventory, mut view_mode): (ResMut<Inventory>, ResMut<ViewMode>),
 ) {
     let current_id = player_profile.into_inner().id;
     'event_loop: for event in ev_spawn.read() {
@@ -41,14 +44,7 @@ pub fn spawn_players_system(
             }
         }
         let is_current_player = event.id == current_id;
-        let player = Player {
-            id: event.id,
-            name: event.name.clone(),
-            position: event.data.position,
-            camera_transform: event.data.camera_transform,
-            is_flying: event.data.is_flying,
-            ..default()
-        };
+        let player = Player::from_save(event.id, event.name.clone(), event.data.clone());
 
         let color = if is_current_player {
             Color::srgba(1.0, 0.0, 0.0, 1.0)
@@ -90,6 +86,10 @@ pub fn spawn_players_system(
             entity.insert(CurrentPlayerMarker {});
             info!("Inserted current player marker");
 
+            // The hotbar reads the selected slot from the player once the game starts
+            inventory.inner = player.inventory.inner.clone();
+            *view_mode = player.view_mode;
+
             info!("aaa ---");
             for (transform, controller) in camera_query.iter_mut() {
                 *transform.into_inner() = event.data.camera_transform;
@@ -128,6 +128,31 @@ pub fn spawn_players_system(
     }
 }
 
+/// Removes the players who left the server, along with their name label
+pub fn despawn_players_system(
+    mut commands: Commands,
+    mut ev_despawn: EventReader<PlayerDespawnEvent>,
+    players: Query<(Entity, &Player)>,
+    labels: Query<(Entity, &PlayerLabel)>,
+) {
+    for event in ev_despawn.read() {
+        let Some((player_entity, _)) = players.iter().find(|(_, player)| player.id == event.id)
+        else {
+            debug!("Ignored despawn order, player was not there: {}", event.id);
+            continue;
+        };
+
+        info!("Despawning player object: {}", event.id);
+        commands.entity(player_entity).despawn();
+
+        for (label_entity, label) in labels.iter() {
+            if label.entity == player_entity {
+                commands.entity(label_entity).despawn();
+            }
+        }
+    }
+}
+
 pub fn update_players_system(
     mut players: Query<(&mut Player, &mut Transform)>,
     mut ev_player_update: EventReader<PlayerUpdateEvent>,
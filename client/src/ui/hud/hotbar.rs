@@ -1,7 +1,9 @@
 use bevy::{prelude::*, ui::FocusPolicy};
+use shared::players::Player;
 
 use crate::{
     constants::{HOTBAR_BORDER, HOTBAR_CELL_SIZE, HOTBAR_PADDING, MAX_HOTBAR_SLOTS},
+    player::CurrentPlayerMarker,
     ui::hud::InventoryCell,
     world::MaterialResource,
     GameState,
@@ -12,12 +14,22 @@ pub struct Hotbar {
     pub selected: u32,
 }
 
-pub fn setup_hotbar(mut commands: Commands, materials_resource: Res<MaterialResource>) {
+pub fn setup_hotbar(
+    mut commands: Commands,
+    materials_resource: Res<MaterialResource>,
+    player: Query<&Player, With<CurrentPlayerMarker>>,
+) {
     let atlas = materials_resource.items.as_ref().unwrap();
 
+    // Restore the slot which was selected when the player left
+    let selected = player
+        .single()
+        .map(|player| player.hotbar_slot.min(MAX_HOTBAR_SLOTS - 1))
+        .unwrap_or(0);
+
     commands
         .spawn((
-            Hotbar { selected: 0 },
+            Hotbar { selected },
             StateScoped(GameState::Game),
             (
                 Node {
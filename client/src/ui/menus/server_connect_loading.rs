@@ -60,12 +60,20 @@ pub fn update_server_connect_loading_screen(
     mut game_state: ResMut<NextState<GameState>>,
     mut target: ResMut<TargetServer>,
     mut loading_text_query: Query<&mut Text, With<LoadingTextMarker>>,
+    mut cancel_text_query: Query<&mut Text, (With<CancelButtonMarker>, Without<LoadingTextMarker>)>,
     mut main_counter: Local<u64>,
     mut dot_counter: Local<u64>,
 ) {
     *main_counter += 1;
 
-    if (*main_counter).is_multiple_of(20) {
+    if let TargetServerState::Rejected(reason) = &target.state {
+        for mut text in loading_text_query.iter_mut() {
+            text.0 = format!("Connection refused\n{reason}");
+        }
+        for mut text in cancel_text_query.iter_mut() {
+            text.0 = "[Back]".into();
+        }
+    } else if (*main_counter).is_multiple_of(20) {
         for mut text in loading_text_query.iter_mut() {
             text.0 = format!(
                 "Connecting to server{}",
@@ -1,5 +1,7 @@
 use bevy::prelude::*;
 use shared::world::BlockData;
+use shared::world::ColumnBiomes;
+use shared::world::PalettedBlocks;
 use shared::world::WorldMap;
 use std::collections::HashSet;
 use std::hash::Hash;
@@ -23,7 +25,8 @@ pub enum GlobalMaterial {
 
 #[derive(Clone, Debug)]
 pub struct ClientChunk {
-    pub map: HashMap<IVec3, BlockData>, // Maps block positions within a chunk to block IDs
+    pub blocks: PalettedBlocks, // Blocks of the chunk, indexed by their position within it
+    pub biomes: ColumnBiomes,
     pub entity: Option<Entity>,
     pub last_mesh_ts: Instant, // When was the last time a mesh was created for this chunk ?
 }
@@ -31,7 +34,8 @@ pub struct ClientChunk {
 impl Default for ClientChunk {
     fn default() -> Self {
         Self {
-            map: HashMap::new(),
+            blocks: PalettedBlocks::default(),
+            biomes: ColumnBiomes::default(),
             entity: None,
             last_mesh_ts: Instant::now(),
         }
@@ -60,7 +64,7 @@ impl WorldMap for ClientWorldMap {
                 let sub_x: i32 = ((x % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
                 let sub_y: i32 = ((y % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
                 let sub_z: i32 = ((z % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
-                chunk.map.get(&IVec3::new(sub_x, sub_y, sub_z))
+                chunk.blocks.get(&IVec3::new(sub_x, sub_y, sub_z))
             }
             None => None,
         }
@@ -79,7 +83,7 @@ impl WorldMap for ClientWorldMap {
                 let sub_x: i32 = ((x % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
                 let sub_y: i32 = ((y % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
                 let sub_z: i32 = ((z % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
-                chunk.map.get_mut(&IVec3::new(sub_x, sub_y, sub_z))
+                chunk.blocks.get_mut(&IVec3::new(sub_x, sub_y, sub_z))
             }
             None => None,
         }
@@ -97,7 +101,7 @@ impl WorldMap for ClientWorldMap {
 
         let local_block_pos: IVec3 = to_local_pos(global_block_pos);
 
-        chunk_map.map.remove(&local_block_pos);
+        chunk_map.blocks.remove(&local_block_pos);
 
         Some(kind)
     }
@@ -114,7 +118,7 @@ impl WorldMap for ClientWorldMap {
         let sub_y: i32 = ((y % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
         let sub_z: i32 = ((z % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
 
-        chunk.map.insert(IVec3::new(sub_x, sub_y, sub_z), block);
+        chunk.blocks.insert(IVec3::new(sub_x, sub_y, sub_z), block);
     }
 
     fn mark_block_for_update(&mut self, _block_pos: &IVec3) {
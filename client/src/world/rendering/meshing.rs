@@ -7,8 +7,11 @@ use bevy::{
     prelude::*,
     render::mesh::{Indices, PrimitiveTopology},
 };
-use shared::world::{to_global_pos, BlockDirection, BlockId, BlockTransparency, WorldMap};
+use shared::world::{
+    to_global_pos, BiomeRegistry, BlockDirection, BlockId, BlockTransparency, WorldMap,
+};
 
+use super::tints::ChunkTints;
 use super::voxel::{Face, FaceDirection, VoxelShape};
 
 #[derive(Copy, Clone, Debug)]
@@ -55,17 +58,19 @@ pub(crate) fn generate_chunk_mesh(
     chunk: &ClientChunk,
     chunk_pos: &IVec3,
     uv_map: &HashMap<String, UvCoords>,
+    biomes: &BiomeRegistry,
 ) -> ChunkMeshResponse {
     let start = Instant::now();
 
     let mut solid_mesh_creator = MeshCreator::default();
+    let tints = ChunkTints::compute(world_map, chunk, chunk_pos, biomes);
 
-    for (local_block_pos, block) in chunk.map.iter() {
+    for (local_block_pos, block) in chunk.blocks.iter() {
         let x = local_block_pos.x as f32;
         let y = local_block_pos.y as f32;
         let z = local_block_pos.z as f32;
 
-        let global_block_pos = &to_global_pos(chunk_pos, local_block_pos);
+        let global_block_pos = &to_global_pos(chunk_pos, &local_block_pos);
         let visibility = block.id.get_visibility();
 
         if is_block_surrounded(world_map, global_block_pos, &visibility, &block.id) {
@@ -78,7 +83,11 @@ pub(crate) fn generate_chunk_mesh(
         let mut local_uvs: Vec<[f32; 2]> = vec![];
         let mut local_colors: Vec<[f32; 4]> = vec![];
 
-        let voxel: VoxelShape = VoxelShape::create_from_block(block);
+        let biome_color = match block.id.get_biome_tint() {
+            Some(tint) => tints.get(local_block_pos.x, local_block_pos.z, tint),
+            None => [1.0, 1.0, 1.0, 1.0],
+        };
+        let voxel: VoxelShape = VoxelShape::create_from_block(block, biome_color);
 
         for face in voxel.faces.iter() {
             let uv_coords: &UvCoords;
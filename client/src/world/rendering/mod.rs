@@ -2,6 +2,7 @@ pub mod materials;
 pub mod meshing;
 pub mod render;
 pub mod render_distance;
+pub mod tints;
 pub mod voxel;
 
 pub use materials::*;
@@ -9,7 +9,7 @@ use bevy::{
     tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
 };
 use shared::{
-    world::{global_block_to_chunk_pos, SIX_OFFSETS},
+    world::{global_block_to_chunk_pos, BiomeRegistry, SIX_OFFSETS},
     CHUNK_SIZE,
 };
 
@@ -77,7 +77,7 @@ fn update_chunk(
 
         chunk.entity = Some(new_entity);
     }
-    // debug!("ClientChunk updated : len={}", chunk.map.len());
+    // debug!("ClientChunk updated : len={}", chunk.blocks.len());
 }
 
 pub fn world_render_system(
@@ -90,6 +90,7 @@ pub fn world_render_system(
     mut commands: Commands,
     mut first_chunk_received: ResMut<FirstChunkReceived>,
     player_pos: Query<&Transform, With<CurrentPlayerMarker>>,
+    biomes: Res<BiomeRegistry>,
 ) {
     for event in ev_render.read() {
         queued_events.events.insert(*event);
@@ -113,6 +114,7 @@ pub fn world_render_system(
         info!("cloning map for render, took {:?}", delta);
 
         let uvs = Arc::new(material_resource.blocks.as_ref().unwrap().uvs.clone());
+        let biomes = Arc::new(biomes.clone());
 
         let mut chunks_to_reload: HashSet<IVec3> = HashSet::new();
 
@@ -147,16 +149,23 @@ pub fn world_render_system(
         for pos in chunks_to_reload {
             if let Some(chunk) = world_map.map.get(&pos) {
                 // If chunk is empty, ignore it
-                if chunk.map.is_empty() {
+                if chunk.blocks.is_empty() {
                     continue;
                 }
 
                 // Define variables to move to the thread
                 let map_clone = Arc::clone(&map_ptr);
                 let uvs_clone = Arc::clone(&uvs);
+                let biomes_clone = Arc::clone(&biomes);
                 let ch = chunk.clone();
                 let t = pool.spawn(async move {
-                    world::meshing::generate_chunk_mesh(&map_clone, &ch, &pos, &uvs_clone)
+                    world::meshing::generate_chunk_mesh(
+                        &map_clone,
+                        &ch,
+                        &pos,
+                        &uvs_clone,
+                        &biomes_clone,
+                    )
                 });
 
                 queued_meshes.meshes.push(MeshingTask {
@@ -199,7 +208,8 @@ pub fn world_render_system(
                 true
             }
         } else {
-            true
+            // The chunk has been unloaded in the meantime
+            false
         }
     });
 
@@ -19,7 +19,9 @@ pub fn render_distance_update_system(
         render_distance.distance = DEFAULT_CHUNK_RENDER_DISTANCE_RADIUS;
     }
 
-    if is_action_just_pressed(GameAction::RenderDistanceMinus, &keyboard_input, &key_map) {
+    if is_action_just_pressed(GameAction::RenderDistanceMinus, &keyboard_input, &key_map)
+        && render_distance.distance > 1
+    {
         render_distance.distance -= 1;
     }
 
@@ -0,0 +1,160 @@
+use bevy::color::Mix;
+use bevy::prelude::*;
+use shared::world::{global_block_to_chunk_pos, to_local_pos, BiomeRegistry, BiomeTint};
+use shared::CHUNK_SIZE;
+
+use crate::constants::{DEFAULT_FOLIAGE_COLOR, DEFAULT_GRASS_COLOR, DEFAULT_WATER_COLOR};
+use crate::world::{ClientChunk, ClientWorldMap, RenderDistance};
+
+/// Number of columns around each column whose colors are averaged with its own
+const BIOME_BLEND_RADIUS: i32 = 2;
+
+/// Share of the remaining difference the sky color catches up with every second,
+/// so that it fades smoothly across biome borders
+const SKY_COLOR_BLEND_SPEED: f32 = 0.5;
+
+/// Fog starts at this share of the render distance, and hides everything past it
+const FOG_START_RATIO: f32 = 0.6;
+
+/// Grass, foliage and water colors, indexed by `BiomeTint`
+type TintColors = [[f32; 3]; 3];
+
+const DEFAULT_TINT_COLORS: TintColors = [
+    DEFAULT_GRASS_COLOR,
+    DEFAULT_FOLIAGE_COLOR,
+    DEFAULT_WATER_COLOR,
+];
+
+/// Biome colors of every column of a chunk.\
+/// Each column is averaged with its neighbours, so that colors fade smoothly across biome borders
+pub struct ChunkTints {
+    columns: Vec<TintColors>,
+}
+
+impl ChunkTints {
+    pub fn compute(
+        world_map: &ClientWorldMap,
+        chunk: &ClientChunk,
+        chunk_pos: &IVec3,
+        biomes: &BiomeRegistry,
+    ) -> Self {
+        let area_size = CHUNK_SIZE + 2 * BIOME_BLEND_RADIUS;
+
+        // Colors of the columns of the chunk and of the border of its neighbours
+        let mut area = Vec::with_capacity((area_size * area_size) as usize);
+        for x in -BIOME_BLEND_RADIUS..CHUNK_SIZE + BIOME_BLEND_RADIUS {
+            for z in -BIOME_BLEND_RADIUS..CHUNK_SIZE + BIOME_BLEND_RADIUS {
+                area.push(column_colors(world_map, chunk, chunk_pos, x, z, biomes));
+            }
+        }
+
+        let mut columns = Vec::with_capacity((CHUNK_SIZE * CHUNK_SIZE) as usize);
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let mut sum = [[0.; 3]; 3];
+                let mut count = 0;
+
+                for dx in -BIOME_BLEND_RADIUS..=BIOME_BLEND_RADIUS {
+                    for dz in -BIOME_BLEND_RADIUS..=BIOME_BLEND_RADIUS {
+                        let index = (x + dx + BIOME_BLEND_RADIUS) * area_size
+                            + (z + dz + BIOME_BLEND_RADIUS);
+
+                        // Columns of unknown biome are left out of the average
+                        let Some(colors) = area[index as usize] else {
+                            continue;
+                        };
+
+                        for (sum_color, color) in sum.iter_mut().zip(colors.iter()) {
+                            for (sum_channel, channel) in sum_color.iter_mut().zip(color.iter()) {
+                                *sum_channel += channel;
+                            }
+                        }
+                        count += 1;
+                    }
+                }
+
+                if count == 0 {
+                    columns.push(DEFAULT_TINT_COLORS);
+                } else {
+                    columns.push(sum.map(|color| color.map(|channel| channel / count as f32)));
+                }
+            }
+        }
+
+        Self { columns }
+    }
+
+    /// Color of a tint at the local column `(x, z)`, as a vertex color
+    pub fn get(&self, x: i32, z: i32, tint: BiomeTint) -> [f32; 4] {
+        let [r, g, b] = self.columns[(x * CHUNK_SIZE + z) as usize][tint as usize];
+        [r, g, b, 1.0]
+    }
+}
+
+/// Colors of the biome of a column, given in coordinates local to the chunk
+/// which may go past its borders. `None` if the biome is unknown
+fn column_colors(
+    world_map: &ClientWorldMap,
+    chunk: &ClientChunk,
+    chunk_pos: &IVec3,
+    x: i32,
+    z: i32,
+    biomes: &BiomeRegistry,
+) -> Option<TintColors> {
+    let chunk_offset = IVec3::new(x.div_euclid(CHUNK_SIZE), 0, z.div_euclid(CHUNK_SIZE));
+    let (local_x, local_z) = (x.rem_euclid(CHUNK_SIZE), z.rem_euclid(CHUNK_SIZE));
+
+    let column_chunk = if chunk_offset == IVec3::ZERO {
+        chunk
+    } else {
+        world_map.map.get(&(*chunk_pos + chunk_offset))?
+    };
+
+    let biome = biomes.get(column_chunk.biomes.get(local_x, local_z)?)?;
+
+    Some([
+        biome.get_tint_color(BiomeTint::Grass),
+        biome.get_tint_color(BiomeTint::Foliage),
+        biome.get_tint_color(BiomeTint::Water),
+    ])
+}
+
+/// Sky color of the biome at a global position, `None` if its chunk or its biome is unknown
+fn biome_sky_color(
+    world_map: &ClientWorldMap,
+    biomes: &BiomeRegistry,
+    position: Vec3,
+) -> Option<[f32; 3]> {
+    let block_pos = position.floor().as_ivec3();
+    let chunk = world_map.map.get(&global_block_to_chunk_pos(&block_pos))?;
+    let local_pos = to_local_pos(&block_pos);
+    let biome = biomes.get(chunk.biomes.get(local_pos.x, local_pos.z)?)?;
+    Some(biome.sky_color)
+}
+
+/// Fades the fog of the camera to the sky color of the biome it is in,
+/// so that distant terrain blends into a sky matching the biome
+pub fn update_sky_color_system(
+    mut cameras: Query<(&GlobalTransform, &mut DistanceFog)>,
+    world_map: Res<ClientWorldMap>,
+    biomes: Res<BiomeRegistry>,
+    render_distance: Res<RenderDistance>,
+    time: Res<Time>,
+) {
+    let fog_end = (render_distance.distance.max(1) as i32 * CHUNK_SIZE) as f32;
+
+    for (transform, mut fog) in cameras.iter_mut() {
+        fog.falloff = FogFalloff::Linear {
+            start: fog_end * FOG_START_RATIO,
+            end: fog_end,
+        };
+
+        // Keeps the last color until the chunk of the camera is received
+        let Some([r, g, b]) = biome_sky_color(&world_map, &biomes, transform.translation()) else {
+            continue;
+        };
+
+        let blend = (SKY_COLOR_BLEND_SPEED * time.delta_secs()).min(1.);
+        fog.color = fog.color.mix(&Color::srgb(r, g, b), blend);
+    }
+}
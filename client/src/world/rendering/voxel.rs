@@ -1,4 +1,3 @@
-use crate::constants::GRASS_COLOR;
 use shared::world::{BlockData, BlockId};
 
 /// Specifies which position in the voxel this face occupies
@@ -38,8 +37,9 @@ pub struct VoxelShape {
 }
 
 impl VoxelShape {
-    /// Creates a VoxelShape based on the given BlockData
-    pub fn create_from_block(block: &BlockData) -> VoxelShape {
+    /// Creates a VoxelShape based on the given BlockData.\
+    /// `biome_color` is the color of the biome tint of the block, unused if it has none
+    pub fn create_from_block(block: &BlockData, biome_color: [f32; 4]) -> VoxelShape {
         match block.id {
             BlockId::Grass => {
                 let mut shape = Self::full_cube(block);
@@ -49,7 +49,7 @@ impl VoxelShape {
                     if index == 0 {
                         face.texture += "Top";
                         for col in face.colors.iter_mut() {
-                            *col = GRASS_COLOR;
+                            *col = biome_color;
                         }
                     }
                 }
@@ -68,7 +68,7 @@ impl VoxelShape {
                 // Apply leaves color
                 for face in shape.faces.iter_mut() {
                     for col in face.colors.iter_mut() {
-                        *col = GRASS_COLOR;
+                        *col = biome_color;
                     }
                 }
 
@@ -91,7 +91,18 @@ impl VoxelShape {
                 // Apply grass color to TallGrass
                 for face in shape.faces.iter_mut() {
                     for col in face.colors.iter_mut() {
-                        *col = GRASS_COLOR;
+                        *col = biome_color;
+                    }
+                }
+
+                shape
+            }
+            BlockId::Water => {
+                let mut shape = Self::full_cube(block);
+
+                for face in shape.faces.iter_mut() {
+                    for col in face.colors.iter_mut() {
+                        *col = biome_color;
                     }
                 }
 
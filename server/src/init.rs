@@ -1,9 +1,15 @@
 use crate::{
     network::{
+        access::AccessLists,
+        auth::load_or_create_private_key,
         cleanup::cleanup_all_players_from_world,
         dispatcher::{self, setup_resources_and_events},
     },
-    world::{data::SAVE_PATH, load_from_file::load_world_data},
+    world::{
+        data::{get_custom_biomes_folder_path, SAVE_PATH},
+        generator::ActiveWorldGenerator,
+        load_from_file::load_world_data,
+    },
 };
 use bevy::{
     diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
@@ -19,15 +25,22 @@ use serde::{Deserialize, Serialize};
 use shared::{
     get_shared_renet_config,
     messages::PlayerId,
-    world::{ServerChunkWorldMap, ServerWorldMap},
+    world::{BiomeRegistry, ServerChunkWorldMap, ServerWorldMap},
     GameFolderPaths, GameServerConfig, TICKS_PER_SECOND,
 };
 use std::fmt::Debug;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
-use std::{collections::HashMap, net::IpAddr};
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+};
 
 use std::net::{SocketAddr, UdpSocket};
 
+/// Connections accepted on top of the player limit
+const EXTRA_CONNECTION_SLOTS: usize = 4;
+
 #[derive(Resource, Serialize, Deserialize, Debug, Clone)]
 pub struct ServerTime(pub u64);
 
@@ -59,7 +72,12 @@ pub fn acquire_socket_by_port(ip: IpAddr, port: u16) -> UdpSocket {
     UdpSocket::bind(addr).unwrap()
 }
 
-pub fn add_netcode_network(app: &mut App, socket: UdpSocket) {
+pub fn add_netcode_network(
+    app: &mut App,
+    socket: UdpSocket,
+    authentication: ServerAuthentication,
+    config: &GameServerConfig,
+) {
     app.add_plugins(NetcodeServerPlugin);
 
     let server = RenetServer::new(get_shared_renet_config());
@@ -71,10 +89,11 @@ pub fn add_netcode_network(app: &mut App, socket: UdpSocket) {
         .unwrap();
     let server_config = ServerConfig {
         current_time,
-        max_clients: 64,
+        // Clients over the player limit must still connect, to be told why they are rejected
+        max_clients: config.max_players as usize + EXTRA_CONNECTION_SLOTS,
         protocol_id: shared::PROTOCOL_ID,
-        public_addresses: vec![*granted_addr],
-        authentication: ServerAuthentication::Unsecure,
+        public_addresses: vec![config.public_address.unwrap_or(*granted_addr)],
+        authentication,
     };
 
     let transport = NetcodeServerTransport::new(server_config, socket).unwrap();
@@ -99,17 +118,47 @@ pub fn init(socket: UdpSocket, config: GameServerConfig, game_folder_paths: Game
     app.insert_resource(game_folder_paths.clone());
 
     let world_name = &config.world_name.clone();
+    let world_generator = config.world_generator.clone();
 
-    app.insert_resource(config);
+    let authentication = if config.secure {
+        // Connect tokens hold the address of the server, which is unknown behind a NAT or a proxy
+        if config.public_address.is_none() {
+            error!("A public address is required in secure mode");
+            panic!()
+        }
+        match load_or_create_private_key(&game_folder_paths) {
+            Ok(private_key) => ServerAuthentication::Secure { private_key },
+            Err(err) => {
+                error!("Failed to load the private key of the server : {}", err);
+                panic!()
+            }
+        }
+    } else {
+        ServerAuthentication::Unsecure
+    };
+
+    let access = match AccessLists::load(&game_folder_paths, config.whitelist) {
+        Ok(access) => access,
+        Err(err) => {
+            error!(
+                "Failed to load the banned players and the whitelist : {}",
+                err
+            );
+            panic!()
+        }
+    };
+    app.insert_resource(access);
 
     info!("Starting server on {}", socket.local_addr().unwrap());
 
-    add_netcode_network(&mut app, socket);
+    add_netcode_network(&mut app, socket, authentication, &config);
+
+    app.insert_resource(config);
 
     setup_resources_and_events(&mut app);
 
     // Load world from files
-    let world_data = match load_world_data(world_name, &game_folder_paths) {
+    let world_data = match load_world_data(world_name, &world_generator, &game_folder_paths) {
         Ok(data) => data,
         Err(err) => {
             error!(
@@ -123,8 +172,11 @@ pub fn init(socket: UdpSocket, config: GameServerConfig, game_folder_paths: Game
     let mut world_map = ServerWorldMap {
         name: world_data.name,
         chunks: ServerChunkWorldMap {
-            map: world_data.map,
-            chunks_to_update: Vec::new(),
+            // Chunks are loaded lazily from the region files
+            map: HashMap::new(),
+            updated_blocks: Vec::new(),
+            pending_blocks: world_data.pending_blocks,
+            changed_chunks: HashSet::new(),
         },
         players: HashMap::new(),
         mobs: world_data.mobs,
@@ -137,6 +189,24 @@ pub fn init(socket: UdpSocket, config: GameServerConfig, game_folder_paths: Game
     // Insert world_map and seed into ressources
     app.insert_resource(world_map);
     app.insert_resource(world_data.seed);
+
+    let biomes = Arc::new(BiomeRegistry::load(&get_custom_biomes_folder_path(
+        &game_folder_paths,
+    )));
+    // Sent to the clients, so that they know the colors of custom biomes
+    app.insert_resource(biomes.as_ref().clone());
+    let generator = match ActiveWorldGenerator::new(world_data.generator, world_data.seed.0, biomes)
+    {
+        Ok(generator) => generator,
+        Err(err) => {
+            error!(
+                "Failed to create the generator of world {} : {}",
+                world_name, err
+            );
+            panic!()
+        }
+    };
+    app.insert_resource(generator);
     app.insert_resource(ServerTime(world_data.time));
 
     // Create save folder if does not already exist
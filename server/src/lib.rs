@@ -4,3 +4,7 @@ mod network;
 mod world;
 
 pub use init::{acquire_local_ephemeral_udp_socket, init};
+
+// Used by the command line of the server binary
+pub use network::auth::{issue_account_token, DEFAULT_TOKEN_EXPIRE_SECS};
+pub use world::backup::{format_backup_age, list_backups, restore_backup, BackupInfo};
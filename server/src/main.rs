@@ -1,8 +1,17 @@
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 
 use crate::init::acquire_socket_by_port;
-use clap::Parser;
-use shared::{get_game_folder_paths, GameServerConfig};
+use crate::network::auth::{issue_account_token, DEFAULT_TOKEN_EXPIRE_SECS};
+use crate::world::backup::{format_backup_age, list_backups, restore_backup};
+use clap::{Parser, Subcommand};
+use shared::world::{BlockId, PregenerationArea, WorldGeneratorConfig};
+use shared::{
+    get_game_folder_paths, GameFolderPaths, GameServerConfig, DEFAULT_AUTH_TIMEOUT_SECS,
+    DEFAULT_AUTOSAVE_INTERVAL_SECS, DEFAULT_BACKUP_COUNT, DEFAULT_BACKUP_INTERVAL_SECS,
+    DEFAULT_BACKUP_MAX_AGE_SECS, DEFAULT_HEARTBEAT_TIMEOUT_SECS, DEFAULT_MAX_PLAYERS,
+    DEFAULT_MAX_VIEW_DISTANCE,
+};
 
 mod init;
 mod mob;
@@ -12,6 +21,9 @@ mod world;
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(short, long, default_value_t = 8000)]
     port: u16,
 
@@ -20,10 +32,257 @@ struct Args {
 
     #[arg(short, long)]
     game_folder_path: Option<String>,
+
+    #[arg(
+        long,
+        default_value_t = DEFAULT_AUTOSAVE_INTERVAL_SECS,
+        help = "Interval between two automatic saves of the world, in seconds (0 to disable)"
+    )]
+    autosave_interval: u64,
+
+    #[arg(
+        long,
+        default_value_t = DEFAULT_BACKUP_INTERVAL_SECS,
+        help = "Interval between two backups of the world, in seconds (0 to disable backups)"
+    )]
+    backup_interval: u64,
+
+    #[arg(
+        long,
+        default_value_t = DEFAULT_BACKUP_COUNT,
+        help = "Number of world backups to keep (0 to disable backups)"
+    )]
+    backup_count: u32,
+
+    #[arg(
+        long,
+        default_value_t = DEFAULT_BACKUP_MAX_AGE_SECS,
+        help = "Backups older than this are deleted, in seconds (0 to keep them regardless of age)"
+    )]
+    backup_max_age: u64,
+
+    #[arg(
+        long,
+        default_value = "noise",
+        value_parser = parse_world_generator,
+        help = "Generator of the world if it does not exist yet : noise, void, superflat, \
+                superflat:<Block>*<thickness>,... (from the bottom up) or biome:<Biome>"
+    )]
+    generator: WorldGeneratorConfig,
+
+    #[arg(
+        long,
+        value_parser = parse_pregeneration_area,
+        help = "Area to generate ahead of time once the server is started, in block coordinates : \
+                radius:<x>,<z>,<radius> or rect:<x1>,<z1>,<x2>,<z2>. \
+                An interrupted pre-generation is resumed on the next start"
+    )]
+    pregenerate: Option<PregenerationArea>,
+
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MAX_VIEW_DISTANCE,
+        help = "Largest view distance granted to clients, in chunks"
+    )]
+    max_view_distance: u32,
+
+    #[arg(
+        long,
+        requires = "public_address",
+        help = "Only accept clients holding a connect token, issued with the auth token command"
+    )]
+    secure: bool,
+
+    #[arg(
+        long,
+        help = "Address clients reach the server at, when it differs from the one it listens on. \
+                Required with --secure, as connect tokens are issued for this address"
+    )]
+    public_address: Option<SocketAddr>,
+
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MAX_PLAYERS,
+        help = "Number of players allowed at the same time"
+    )]
+    max_players: u32,
+
+    #[arg(
+        long,
+        help = "Only accept the players listed in whitelist.ron, in the game folder"
+    )]
+    whitelist: bool,
+
+    #[arg(
+        long,
+        default_value_t = DEFAULT_HEARTBEAT_TIMEOUT_SECS,
+        help = "Players not heard from for this long are kicked, in seconds"
+    )]
+    heartbeat_timeout: u64,
+
+    #[arg(
+        long,
+        default_value_t = DEFAULT_AUTH_TIMEOUT_SECS,
+        help = "Clients which do not authenticate for this long after connecting are kicked, in seconds"
+    )]
+    auth_timeout: u64,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage the backups of the world, without starting the server
+    Backup {
+        #[command(subcommand)]
+        action: BackupAction,
+    },
+    /// Manage the accounts of the players, for servers started with --secure
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AuthAction {
+    /// Issue a connect token binding a username to its account, to be given to the client
+    Token {
+        /// Name the player will play under
+        username: String,
+        /// Public address of the server, the token is only valid there
+        #[arg(long)]
+        address: SocketAddr,
+        /// Validity of the token, in seconds
+        #[arg(long, default_value_t = DEFAULT_TOKEN_EXPIRE_SECS)]
+        expire: u64,
+        /// File to write the token to
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BackupAction {
+    /// List the available backups, newest first
+    List,
+    /// Replace the world with one of its backups. The current world is backed up first
+    Restore {
+        /// Name of the backup, as shown by `backup list`
+        name: String,
+    },
+}
+
+fn parse_world_generator(value: &str) -> Result<WorldGeneratorConfig, String> {
+    let (kind, params) = match value.split_once(':') {
+        Some((kind, params)) => (kind, Some(params)),
+        None => (value, None),
+    };
+
+    match (kind, params) {
+        ("noise", None) => Ok(WorldGeneratorConfig::Noise),
+        ("void", None) => Ok(WorldGeneratorConfig::Void),
+        ("superflat", None) => Ok(WorldGeneratorConfig::default_superflat()),
+        ("superflat", Some(layers)) => {
+            let layers = layers
+                .split(',')
+                .map(|layer| {
+                    let (block, thickness) = layer.split_once('*').unwrap_or((layer, "1"));
+                    let block = ron::de::from_str::<BlockId>(block.trim())
+                        .map_err(|_| format!("Unknown block : {block}"))?;
+                    let thickness = thickness
+                        .trim()
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|value| *value <= i32::MAX as u32)
+                        .ok_or_else(|| format!("Invalid layer thickness : {thickness}"))?;
+                    Ok((block, thickness))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(WorldGeneratorConfig::Superflat { layers })
+        }
+        // Biome names are checked once the biome registry is loaded
+        ("biome", Some(biome)) => Ok(WorldGeneratorConfig::SingleBiome(biome.trim().to_string())),
+        _ => Err(format!("Unknown world generator : {value}")),
+    }
+}
+
+fn parse_pregeneration_area(value: &str) -> Result<PregenerationArea, String> {
+    let (kind, values) = value
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid pre-generation area : {value}"))?;
+    PregenerationArea::parse(kind, &values.split(',').collect::<Vec<_>>())
+}
+
+fn run_backup_action(action: BackupAction, game_folder_paths: &GameFolderPaths, world: &str) {
+    match action {
+        BackupAction::List => match list_backups(game_folder_paths, world) {
+            Ok(backups) if backups.is_empty() => println!("No backups for world {world}"),
+            Ok(backups) => {
+                for backup in backups {
+                    println!("{}\t{} ago", backup.name, format_backup_age(&backup));
+                }
+            }
+            Err(err) => {
+                eprintln!("Could not list backups of world {world} : {err}");
+                std::process::exit(1);
+            }
+        },
+        BackupAction::Restore { name } => match restore_backup(game_folder_paths, world, &name) {
+            Ok(()) => println!("World {world} restored from backup {name}"),
+            Err(err) => {
+                eprintln!("Could not restore backup {name} : {err}");
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+fn run_auth_action(action: AuthAction, game_folder_paths: &GameFolderPaths) {
+    match action {
+        AuthAction::Token {
+            username,
+            address,
+            expire,
+            output,
+        } => {
+            let token = issue_account_token(game_folder_paths, &username, address, expire)
+                .and_then(|token| {
+                    let contents =
+                        ron::ser::to_string_pretty(&token, ron::ser::PrettyConfig::default())?;
+                    std::fs::write(&output, contents)?;
+                    Ok(token)
+                });
+
+            match token {
+                Ok(token) => println!(
+                    "Token of {username} (account {}) written to {}",
+                    token.account_id,
+                    output.display()
+                ),
+                Err(err) => {
+                    eprintln!("Could not issue a token for {username} : {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
 }
 
 fn main() {
     let args = Args::parse();
+    let game_folder_paths = get_game_folder_paths(args.game_folder_path, None);
+
+    match args.command {
+        Some(Command::Backup { action }) => {
+            run_backup_action(action, &game_folder_paths, &args.world);
+            return;
+        }
+        Some(Command::Auth { action }) => {
+            run_auth_action(action, &game_folder_paths);
+            return;
+        }
+        None => {}
+    }
+
     let socket = acquire_socket_by_port(std::net::IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), args.port);
 
     init::init(
@@ -31,7 +290,20 @@ fn main() {
         GameServerConfig {
             world_name: args.world,
             is_solo: false,
+            autosave_interval_secs: args.autosave_interval,
+            backup_interval_secs: args.backup_interval,
+            backup_count: args.backup_count,
+            backup_max_age_secs: args.backup_max_age,
+            world_generator: args.generator,
+            pregenerate: args.pregenerate,
+            max_view_distance: args.max_view_distance,
+            secure: args.secure,
+            public_address: args.public_address,
+            max_players: args.max_players,
+            whitelist: args.whitelist,
+            heartbeat_timeout_secs: args.heartbeat_timeout,
+            auth_timeout_secs: args.auth_timeout,
         },
-        get_game_folder_paths(args.game_folder_path, None),
+        game_folder_paths,
     );
 }
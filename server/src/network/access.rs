@@ -0,0 +1,125 @@
+//! Decides which clients may join the server, and disconnects the rejected ones
+//! once they were told why.
+//!
+//! Banned players and the whitelist are lists of usernames, kept in the game folder
+//! and read when the server starts.
+
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+use shared::messages::AuthRegisterError;
+use shared::{GameFolderPaths, GameServerConfig, TICKS_PER_SECOND};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::init::{ServerLobby, ServerTime};
+
+use super::extensions::SendGameMessageExtension;
+
+const BANNED_PLAYERS_FILE_NAME: &str = "banned_players.ron";
+const WHITELIST_FILE_NAME: &str = "whitelist.ron";
+
+/// Ticks between a rejection and the disconnection of the client,
+/// for the rejection to reach it before the connection is closed
+const REJECTION_DISCONNECT_DELAY_TICKS: u64 = TICKS_PER_SECOND;
+
+#[derive(Resource, Default, Debug)]
+pub struct AccessLists {
+    pub banned: HashSet<String>,
+    /// Only players in this list may join, if the whitelist is enabled
+    pub whitelist: Option<HashSet<String>>,
+}
+
+fn load_usernames(path: &Path) -> Result<HashSet<String>, Box<dyn std::error::Error>> {
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    Ok(ron::de::from_str(&fs::read_to_string(path)?)?)
+}
+
+impl AccessLists {
+    pub fn load(
+        game_folder_paths: &GameFolderPaths,
+        whitelist_enabled: bool,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let folder = &game_folder_paths.game_folder_path;
+
+        let whitelist = if whitelist_enabled {
+            Some(load_usernames(&folder.join(WHITELIST_FILE_NAME))?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            banned: load_usernames(&folder.join(BANNED_PLAYERS_FILE_NAME))?,
+            whitelist,
+        })
+    }
+
+    /// Whether a player may join the server, checked once its version is known to match
+    pub fn check(
+        &self,
+        username: &str,
+        lobby: &ServerLobby,
+        config: &GameServerConfig,
+    ) -> Result<(), AuthRegisterError> {
+        if self.banned.contains(username) {
+            return Err(AuthRegisterError::Banned);
+        }
+        if let Some(whitelist) = &self.whitelist {
+            if !whitelist.contains(username) {
+                return Err(AuthRegisterError::NotWhitelisted);
+            }
+        }
+        if lobby.players.values().any(|player| player.name == username) {
+            return Err(AuthRegisterError::NameTaken);
+        }
+        if lobby.players.len() >= config.max_players as usize {
+            return Err(AuthRegisterError::ServerFull {
+                max_players: config.max_players,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Rejected clients, with the tick they get disconnected at
+#[derive(Resource, Default)]
+pub struct PendingDisconnects {
+    clients: HashMap<ClientId, u64>,
+}
+
+impl PendingDisconnects {
+    pub fn contains(&self, client_id: &ClientId) -> bool {
+        self.clients.contains_key(client_id)
+    }
+}
+
+/// Tells a client why it may not join, then disconnects it a bit later
+pub fn reject_client(
+    server: &mut RenetServer,
+    pending: &mut PendingDisconnects,
+    client_id: ClientId,
+    error: AuthRegisterError,
+    tick: u64,
+) {
+    info!("Player {} rejected : {}", client_id, error);
+    server.send_game_message(client_id, error.into());
+    pending
+        .clients
+        .insert(client_id, tick + REJECTION_DISCONNECT_DELAY_TICKS);
+}
+
+pub fn disconnect_rejected_clients_system(
+    mut server: ResMut<RenetServer>,
+    mut pending: ResMut<PendingDisconnects>,
+    time: Res<ServerTime>,
+) {
+    pending.clients.retain(|client_id, disconnect_tick| {
+        if time.0 < *disconnect_tick {
+            return true;
+        }
+        server.disconnect(*client_id);
+        false
+    });
+}
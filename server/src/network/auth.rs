@@ -0,0 +1,206 @@
+//! Accounts of the players, for servers only accepting clients with a connect token.
+//!
+//! The server and whoever issues the tokens share a private key, stored in the game folder.
+//! Each token binds a stable account id, used as the client id, to a username,
+//! so that players can neither pick the id of somebody else nor take over their save.
+
+use bevy::prelude::*;
+use bevy_renet::netcode::{ConnectToken, NETCODE_KEY_BYTES, NETCODE_USER_DATA_BYTES};
+use shared::messages::PlayerId;
+use shared::{AccountToken, GameFolderPaths, PROTOCOL_ID};
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+pub const AUTH_PATH: &str = "auth/";
+const PRIVATE_KEY_FILE_NAME: &str = "private.key";
+const ACCOUNTS_FILE_NAME: &str = "accounts.ron";
+
+/// Validity of the tokens issued by the CLI, in seconds
+pub const DEFAULT_TOKEN_EXPIRE_SECS: u64 = 24 * 60 * 60;
+
+/// Time without any packet after which a client using a token is disconnected, in seconds
+const TOKEN_TIMEOUT_SECS: i32 = 15;
+
+fn get_auth_folder_path(game_folder_paths: &GameFolderPaths) -> PathBuf {
+    game_folder_paths.game_folder_path.join(AUTH_PATH)
+}
+
+/// Reads the private key shared with the token issuer, creating a new one on first use
+pub fn load_or_create_private_key(
+    game_folder_paths: &GameFolderPaths,
+) -> Result<[u8; NETCODE_KEY_BYTES], Box<dyn std::error::Error>> {
+    let path = get_auth_folder_path(game_folder_paths).join(PRIVATE_KEY_FILE_NAME);
+
+    if path.exists() {
+        let contents = fs::read_to_string(&path)?;
+        let hex = contents.trim();
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2).unwrap_or_default(), 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| format!("invalid private key in {}", path.display()))?;
+        return bytes
+            .try_into()
+            .map_err(|_| format!("invalid private key length in {}", path.display()).into());
+    }
+
+    let key: [u8; NETCODE_KEY_BYTES] = rand::random();
+    fs::create_dir_all(get_auth_folder_path(game_folder_paths))?;
+    fs::write(
+        &path,
+        key.iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>(),
+    )?;
+    info!("Created a new private key in {}", path.display());
+
+    Ok(key)
+}
+
+/// Username stored in the user data of a connect token, prefixed by its length
+fn username_to_user_data(
+    username: &str,
+) -> Result<[u8; NETCODE_USER_DATA_BYTES], Box<dyn std::error::Error>> {
+    let bytes = username.as_bytes();
+    if bytes.is_empty() || bytes.len() >= NETCODE_USER_DATA_BYTES {
+        return Err(format!("invalid username length : {}", bytes.len()).into());
+    }
+
+    let mut user_data = [0; NETCODE_USER_DATA_BYTES];
+    user_data[0] = bytes.len() as u8;
+    user_data[1..=bytes.len()].copy_from_slice(bytes);
+    Ok(user_data)
+}
+
+/// Username bound to a client by its connect token
+pub fn username_from_user_data(user_data: &[u8; NETCODE_USER_DATA_BYTES]) -> Option<String> {
+    let length = user_data[0] as usize;
+    if length == 0 {
+        return None;
+    }
+    String::from_utf8(user_data[1..=length].to_vec()).ok()
+}
+
+/// Account id of a username, created the first time a token is issued for it
+fn get_or_create_account_id(
+    game_folder_paths: &GameFolderPaths,
+    username: &str,
+) -> Result<PlayerId, Box<dyn std::error::Error>> {
+    let path = get_auth_folder_path(game_folder_paths).join(ACCOUNTS_FILE_NAME);
+
+    let mut accounts: HashMap<String, PlayerId> = if path.exists() {
+        ron::de::from_str(&fs::read_to_string(&path)?)?
+    } else {
+        HashMap::new()
+    };
+
+    if let Some(account_id) = accounts.get(username) {
+        return Ok(*account_id);
+    }
+
+    let account_id = loop {
+        let account_id: PlayerId = rand::random();
+        if !accounts.values().any(|id| *id == account_id) {
+            break account_id;
+        }
+    };
+    accounts.insert(username.to_string(), account_id);
+
+    fs::create_dir_all(get_auth_folder_path(game_folder_paths))?;
+    fs::write(
+        &path,
+        ron::ser::to_string_pretty(&accounts, ron::ser::PrettyConfig::default())?,
+    )?;
+
+    Ok(account_id)
+}
+
+/// Issues a connect token for a username, valid for the servers at `server_address`.\
+/// This stands in for an authentication service, which would first check who the player is
+pub fn issue_account_token(
+    game_folder_paths: &GameFolderPaths,
+    username: &str,
+    server_address: SocketAddr,
+    expire_secs: u64,
+) -> Result<AccountToken, Box<dyn std::error::Error>> {
+    let private_key = load_or_create_private_key(game_folder_paths)?;
+    let account_id = get_or_create_account_id(game_folder_paths, username)?;
+
+    let current_time: Duration = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    let connect_token = ConnectToken::generate(
+        current_time,
+        PROTOCOL_ID,
+        expire_secs,
+        account_id,
+        TOKEN_TIMEOUT_SECS,
+        vec![server_address],
+        Some(&username_to_user_data(username)?),
+        &private_key,
+    )?;
+
+    let mut token_bytes = Vec::new();
+    connect_token.write(&mut token_bytes)?;
+
+    Ok(AccountToken {
+        account_id,
+        username: username.to_string(),
+        server_address,
+        connect_token: token_bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_game_folder_paths() -> GameFolderPaths {
+        let folder = std::env::temp_dir().join(format!("auth-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        GameFolderPaths {
+            game_folder_path: folder.clone(),
+            assets_folder_path: folder,
+        }
+    }
+
+    #[test]
+    fn issued_tokens_are_bound_to_a_stable_account() {
+        let game_folder_paths = test_game_folder_paths();
+        let server_address: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+        let token = issue_account_token(&game_folder_paths, "alice", server_address, 60).unwrap();
+        assert_eq!(token.username, "alice");
+        assert_eq!(token.server_address, server_address);
+        assert!(ConnectToken::read(&mut token.connect_token.as_slice()).is_ok());
+
+        let again = issue_account_token(&game_folder_paths, "alice", server_address, 60).unwrap();
+        assert_eq!(again.account_id, token.account_id);
+
+        let other = issue_account_token(&game_folder_paths, "bob", server_address, 60).unwrap();
+        assert_ne!(other.account_id, token.account_id);
+
+        fs::remove_dir_all(&game_folder_paths.game_folder_path).unwrap();
+    }
+
+    #[test]
+    fn usernames_round_trip_through_user_data() {
+        for username in [
+            "a",
+            "alice",
+            "élodie",
+            &"x".repeat(NETCODE_USER_DATA_BYTES - 1),
+        ] {
+            let user_data = username_to_user_data(username).unwrap();
+            assert_eq!(
+                username_from_user_data(&user_data).as_deref(),
+                Some(username)
+            );
+        }
+
+        assert!(username_to_user_data("").is_err());
+        assert!(username_to_user_data(&"x".repeat(NETCODE_USER_DATA_BYTES)).is_err());
+        assert_eq!(username_from_user_data(&[0; NETCODE_USER_DATA_BYTES]), None);
+    }
+}
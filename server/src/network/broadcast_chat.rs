@@ -1,5 +1,11 @@
 use bevy::prelude::*;
-use shared::messages::ChatConversation;
+use bevy_renet::renet::RenetServer;
+use shared::messages::{ChatConversation, FullChatMessage, ServerToClientMessage};
+
+use super::extensions::SendGameMessageExtension;
+
+/// Author of the messages written by the server itself
+const SERVER_CHAT_AUTHOR: &str = "Server";
 
 #[derive(Event)]
 pub struct ChatMessageEvent;
@@ -8,3 +14,38 @@ pub fn setup_chat_resources(app: &mut App) {
     app.insert_resource(ChatConversation { ..default() });
     app.add_event::<ChatMessageEvent>();
 }
+
+/// Adds a message of the server to the chat, e.g. to tell players about each other
+pub fn push_server_notice(
+    chat_conversation: &mut ChatConversation,
+    ev_chat: &mut EventWriter<ChatMessageEvent>,
+    content: String,
+) {
+    let timestamp: u64 = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+
+    chat_conversation.messages.push(FullChatMessage {
+        author: SERVER_CHAT_AUTHOR.into(),
+        content,
+        timestamp,
+    });
+    ev_chat.write(ChatMessageEvent);
+}
+
+/// Sends the conversation to every client whenever a message was added to it
+pub fn broadcast_chat_system(
+    mut events: EventReader<ChatMessageEvent>,
+    chat_conversation: Res<ChatConversation>,
+    mut server: ResMut<RenetServer>,
+) {
+    if events.is_empty() {
+        return;
+    }
+    events.clear();
+
+    server.broadcast_game_message(ServerToClientMessage::ChatConversation(
+        chat_conversation.clone(),
+    ));
+}
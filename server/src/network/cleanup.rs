@@ -1,27 +1,49 @@
 use bevy_ecs::event::EventWriter;
+use bevy_renet::renet::RenetServer;
+use shared::messages::{ChatConversation, PlayerDespawnEvent, ServerToClientMessage};
 use shared::{messages::PlayerId, world::ServerWorldMap};
 
 use crate::world::save::SaveRequestEvent;
+use crate::world::view::PlayerViews;
+
+use super::broadcast_chat::{push_server_notice, ChatMessageEvent};
+use super::extensions::SendGameMessageExtension;
 
 pub fn cleanup_all_players_from_world(world_map: &mut ServerWorldMap) {
     for p in world_map.players.values_mut() {
         p.last_input_processed = 0;
     }
-    for (_, chunk) in world_map.chunks.map.iter_mut() {
-        chunk.sent_to_clients.clear();
-    }
 }
 
 pub fn cleanup_player_from_world(
     world_map: &mut ServerWorldMap,
+    views: &mut PlayerViews,
     player_id: &PlayerId,
     save_event_writer: &mut EventWriter<SaveRequestEvent>,
 ) {
-    if world_map.players.remove(player_id).is_some() {
-        save_event_writer.write(SaveRequestEvent::Player(*player_id));
+    // The player is gone by the time the save is processed, so its state is sent along
+    if let Some(player) = world_map.players.remove(player_id) {
+        save_event_writer.write(SaveRequestEvent::PlayerLeft(
+            *player_id,
+            player.name.clone(),
+            player.to_save(),
+        ));
     }
 
-    for (_, chunk) in world_map.chunks.map.iter_mut() {
-        chunk.sent_to_clients.retain(|&id| id != *player_id);
-    }
+    // Chunks are sent again from scratch if the player comes back
+    views.remove(player_id);
+}
+
+/// Removes a player from the world of the other clients, and tells them why it is gone
+pub fn announce_player_left(
+    server: &mut RenetServer,
+    chat_conversation: &mut ChatConversation,
+    ev_chat: &mut EventWriter<ChatMessageEvent>,
+    player_id: PlayerId,
+    notice: String,
+) {
+    server.broadcast_game_message(ServerToClientMessage::PlayerDespawn(PlayerDespawnEvent {
+        id: player_id,
+    }));
+    push_server_notice(chat_conversation, ev_chat, notice);
 }
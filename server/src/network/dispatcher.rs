@@ -1,22 +1,38 @@
 use crate::init::{LobbyPlayer, ServerLobby, ServerTime};
 use crate::mob::behavior::mob_behavior_system;
+use crate::network::access::{
+    disconnect_rejected_clients_system, reject_client, AccessLists, PendingDisconnects,
+};
+use crate::network::auth::username_from_user_data;
 use crate::network::broadcast_chat::*;
-use crate::network::cleanup::cleanup_player_from_world;
+use crate::network::cleanup::{announce_player_left, cleanup_player_from_world};
+use crate::network::heartbeat::{kick_unresponsive_clients_system, ClientActivities};
 use crate::world;
-use crate::world::background_generation::background_world_generation_system;
+use crate::world::background_generation::{
+    background_world_generation_system, ChunkGenerationQueue,
+};
+use crate::world::backup::{backup_system, PendingBackup};
 use crate::world::broadcast_world::broadcast_world_state;
 use crate::world::load_from_file::load_player_data;
-use crate::world::save::SaveRequestEvent;
+use crate::world::pregeneration::{
+    handle_pregeneration_commands, pregeneration_system, start_pregeneration_system, Pregeneration,
+    PregenerationCommand,
+};
+use crate::world::save::{autosave_system, PendingSave, SaveRequestEvent};
 use crate::world::simulation::{handle_player_inputs_system, PlayerInputsEvent};
+use crate::world::streaming::{unload_inactive_chunks_system, ChunkActivity};
+use crate::world::view::PlayerViews;
+use crate::world::writer::{flush_world_writes_on_exit, poll_world_writes_system, WorldWriter};
 use crate::world::BlockInteractionEvent;
 use bevy::prelude::*;
+use bevy_renet::netcode::NetcodeServerTransport;
 use bevy_renet::renet::{RenetServer, ServerEvent};
 use shared::messages::{
-    AuthRegisterResponse, ChatConversation, ClientToServerMessage, FullChatMessage, PlayerSave,
-    PlayerSpawnEvent, ServerToClientMessage,
+    AuthRegisterError, AuthRegisterResponse, ChatConversation, ClientToServerMessage,
+    FullChatMessage, GameVersion, PlayerSpawnEvent, ServerToClientMessage,
 };
 use shared::players::Player;
-use shared::world::ServerWorldMap;
+use shared::world::{BiomeRegistry, ServerWorldMap};
 use shared::{GameFolderPaths, GameServerConfig, TICKS_PER_SECOND};
 
 use super::extensions::SendGameMessageExtension;
@@ -24,7 +40,18 @@ use super::extensions::SendGameMessageExtension;
 pub fn setup_resources_and_events(app: &mut App) {
     app.add_event::<SaveRequestEvent>()
         .add_event::<BlockInteractionEvent>()
-        .add_event::<PlayerInputsEvent>();
+        .add_event::<PlayerInputsEvent>()
+        .add_event::<PregenerationCommand>();
+
+    app.init_resource::<ChunkActivity>();
+    app.init_resource::<PendingSave>();
+    app.init_resource::<PendingBackup>();
+    app.init_resource::<WorldWriter>();
+    app.init_resource::<Pregeneration>();
+    app.init_resource::<ChunkGenerationQueue>();
+    app.init_resource::<PlayerViews>();
+    app.init_resource::<PendingDisconnects>();
+    app.init_resource::<ClientActivities>();
 
     setup_chat_resources(app);
 }
@@ -36,6 +63,21 @@ pub fn register_systems(app: &mut App) {
         (server_update_system, world::save::save_world_system).chain(),
     );
 
+    app.add_systems(
+        Update,
+        autosave_system.before(world::save::save_world_system),
+    );
+
+    app.add_systems(Last, flush_world_writes_on_exit);
+
+    app.add_systems(Update, backup_system);
+
+    app.add_systems(Update, disconnect_rejected_clients_system);
+
+    app.add_systems(Update, kick_unresponsive_clients_system);
+
+    app.add_systems(Update, broadcast_chat_system.after(server_update_system));
+
     app.add_systems(Update, broadcast_world_state);
 
     app.add_systems(Update, world::handle_block_interactions);
@@ -46,6 +88,18 @@ pub fn register_systems(app: &mut App) {
 
     app.add_systems(Update, background_world_generation_system);
 
+    app.add_systems(
+        Update,
+        unload_inactive_chunks_system.after(poll_world_writes_system),
+    );
+    app.add_systems(Update, poll_world_writes_system);
+
+    app.add_systems(Startup, start_pregeneration_system);
+    app.add_systems(
+        Update,
+        (handle_pregeneration_commands, pregeneration_system).chain(),
+    );
+
     app.add_systems(PostUpdate, update_server_time);
 
     app.add_systems(FixedUpdate, mob_behavior_system);
@@ -53,50 +107,152 @@ pub fn register_systems(app: &mut App) {
 
 fn server_update_system(
     mut server_events: EventReader<ServerEvent>,
-    (mut server, mut chat_conversation, mut lobby): (
+    (mut server, transport, mut chat_conversation, mut lobby): (
         ResMut<RenetServer>,
+        Res<NetcodeServerTransport>,
         ResMut<ChatConversation>,
         ResMut<ServerLobby>,
     ),
-    (mut ev_chat, mut ev_app_exit, mut ev_save_request, mut ev_player_inputs): (
+    (
+        mut ev_chat,
+        mut ev_app_exit,
+        mut ev_save_request,
+        mut ev_player_inputs,
+        mut ev_pregeneration,
+    ): (
         EventWriter<ChatMessageEvent>,
         EventWriter<AppExit>,
         EventWriter<SaveRequestEvent>,
         EventWriter<PlayerInputsEvent>,
+        EventWriter<PregenerationCommand>,
     ),
     config: Res<GameServerConfig>,
     mut world_map: ResMut<ServerWorldMap>,
+    mut views: ResMut<PlayerViews>,
     time: Res<ServerTime>,
     game_folder_paths: Res<GameFolderPaths>,
+    biomes: Res<BiomeRegistry>,
+    (access, mut pending_disconnects, mut activities): (
+        Res<AccessLists>,
+        ResMut<PendingDisconnects>,
+        ResMut<ClientActivities>,
+    ),
 ) {
     for event in server_events.read() {
         debug!("event received");
         match event {
             ServerEvent::ClientConnected { client_id } => {
                 info!("Player {} connected.", client_id);
+                activities.connected(*client_id, time.0);
             }
             ServerEvent::ClientDisconnected { client_id, reason } => {
                 info!("Player {} disconnected: {}", client_id, reason);
-                lobby.players.remove(client_id);
-                cleanup_player_from_world(&mut world_map, client_id, &mut ev_save_request);
+                activities.remove(client_id);
+                if let Some(player) = lobby.players.remove(client_id) {
+                    announce_player_left(
+                        &mut server,
+                        &mut chat_conversation,
+                        &mut ev_chat,
+                        *client_id,
+                        format!("{} left the game", player.name),
+                    );
+                }
+                cleanup_player_from_world(
+                    &mut world_map,
+                    &mut views,
+                    client_id,
+                    &mut ev_save_request,
+                );
             }
         }
     }
 
     for client_id in server.clients_id() {
-        while let Some(Ok(message)) = server.receive_game_message(client_id) {
+        while let Some(message) = server.receive_game_message(client_id) {
+            let Ok(message) = message else {
+                // Already logged, the next messages of the client may still be readable
+                continue;
+            };
+
+            activities.seen(client_id, time.0);
+
+            // Rejected clients stay connected for a while, they may only wait to be disconnected
+            let joined =
+                lobby.players.contains_key(&client_id) && !pending_disconnects.contains(&client_id);
+            let allowed = match message {
+                ClientToServerMessage::AuthRegisterRequest(_) => {
+                    !joined && !pending_disconnects.contains(&client_id)
+                }
+                ClientToServerMessage::Heartbeat => true,
+                _ => joined,
+            };
+            if !allowed {
+                warn!(
+                    "Ignoring message from client {}, which {}",
+                    client_id,
+                    if joined {
+                        "already joined"
+                    } else {
+                        "did not join"
+                    }
+                );
+                continue;
+            }
+
             match message {
                 ClientToServerMessage::AuthRegisterRequest(auth_req) => {
                     info!("Auth request received {:?}", auth_req);
 
-                    if lobby.players.values().any(|v| v.name == auth_req.username) {
-                        debug!("Username already in map: {}", &auth_req.username);
-                        return;
+                    let server_version = GameVersion::current();
+                    if auth_req.version != server_version {
+                        warn!(
+                            "Player {} uses version {}, the server uses version {}",
+                            client_id, auth_req.version, server_version
+                        );
+                        reject_client(
+                            &mut server,
+                            &mut pending_disconnects,
+                            client_id,
+                            AuthRegisterError::VersionMismatch {
+                                server: server_version,
+                                client: auth_req.version,
+                            },
+                            time.0,
+                        );
+                        continue;
+                    }
+
+                    // With connect tokens, the username is the one the account was issued for
+                    let username = if config.secure {
+                        match transport
+                            .user_data(client_id)
+                            .and_then(|user_data| username_from_user_data(&user_data))
+                        {
+                            Some(username) => username,
+                            None => {
+                                warn!("Player {} has no username in its connect token", client_id);
+                                server.disconnect(client_id);
+                                continue;
+                            }
+                        }
+                    } else {
+                        auth_req.username
+                    };
+
+                    if let Err(err) = access.check(&username, &lobby, &config) {
+                        reject_client(
+                            &mut server,
+                            &mut pending_disconnects,
+                            client_id,
+                            err,
+                            time.0,
+                        );
+                        continue;
                     }
 
                     lobby
                         .players
-                        .insert(client_id, LobbyPlayer::new(auth_req.username.clone()));
+                        .insert(client_id, LobbyPlayer::new(username.clone()));
                     debug!("New lobby : {:?}", lobby);
 
                     // Load player data if it doesn't already exist
@@ -104,19 +260,11 @@ fn server_update_system(
                     {
                         player
                     } else {
-                        let data =
-                            load_player_data(&world_map.name, &client_id, &game_folder_paths);
+                        let data = load_player_data(&world_map.name, &username, &game_folder_paths);
 
                         world_map.players.insert(
                             client_id,
-                            Player {
-                                id: client_id,
-                                is_flying: data.is_flying,
-                                position: data.position,
-                                camera_transform: data.camera_transform,
-                                name: auth_req.username.clone(),
-                                ..default()
-                            },
+                            Player::from_save(client_id, username.clone(), data),
                         );
 
                         world_map.players.get(&client_id).unwrap()
@@ -127,58 +275,60 @@ fn server_update_system(
                         .unwrap()
                         .as_millis() as u64;
 
-                    let all_player_spawn_events = world_map
-                        .players
-                        .iter()
-                        .map(|(id, player)| PlayerSpawnEvent {
-                            id: *id,
-                            name: player.name.clone(),
-                            data: PlayerSave {
-                                position: player.position,
-                                camera_transform: player.camera_transform,
-                                is_flying: player.is_flying,
-                            },
-                        })
-                        .collect();
+                    // Other players are spawned on the client once they are in view
+                    let player_spawn_event = PlayerSpawnEvent {
+                        id: client_id,
+                        name: registered_player.name.clone(),
+                        data: registered_player.to_save(),
+                    };
 
-                    // TODO: add cleanup system if no heartbeat
                     let auth_res = AuthRegisterResponse {
-                        username: auth_req.username,
+                        username,
                         session_token: client_id,
                         tick: time.0,
                         timestamp_ms,
-                        players: all_player_spawn_events,
+                        players: vec![player_spawn_event],
+                        biomes: biomes.definitions().to_vec(),
                     };
 
                     server.send_game_message(client_id, auth_res.into());
 
-                    // Send message to all players that a new one spawned
-                    for (id, player) in lobby.players.iter() {
-                        let spawn_message = PlayerSpawnEvent {
-                            id: *id,
-                            name: player.name.clone(),
-                            data: PlayerSave {
-                                position: registered_player.position,
-                                camera_transform: registered_player.camera_transform,
-                                is_flying: registered_player.is_flying,
-                            },
-                        };
-
-                        let spawn_message_wrapped =
-                            ServerToClientMessage::PlayerSpawn(spawn_message);
-
-                        info!("Sending spawn order {:?}", spawn_message_wrapped);
-                        server.broadcast_game_message(spawn_message_wrapped);
-                    }
+                    push_server_notice(
+                        &mut chat_conversation,
+                        &mut ev_chat,
+                        format!("{} joined the game", registered_player.name),
+                    );
                 }
                 ClientToServerMessage::ChatMessage(chat_msg) => {
                     info!("Chat message received: {:?}", &chat_msg);
+
+                    if let Some(args) = chat_msg.content.strip_prefix("/pregen") {
+                        // TODO : Check for permissions on multiplayer mode (server admin)
+                        if !config.is_solo {
+                            warn!(
+                                "Player {} is not allowed to pre-generate the world",
+                                client_id
+                            );
+                            continue;
+                        }
+
+                        match PregenerationCommand::parse(args) {
+                            Ok(command) => {
+                                ev_pregeneration.write(command);
+                            }
+                            Err(err) => warn!("Invalid /pregen command : {}", err),
+                        }
+                        continue;
+                    }
+
                     let current_timestamp: u64 = std::time::SystemTime::now()
                         .duration_since(std::time::UNIX_EPOCH)
                         .unwrap()
                         .as_millis() as u64;
 
-                    let current_author = lobby.players.get(&client_id).unwrap();
+                    let Some(current_author) = lobby.players.get(&client_id) else {
+                        continue;
+                    };
 
                     chat_conversation.messages.push(FullChatMessage {
                         author: current_author.name.clone(),
@@ -198,8 +348,8 @@ fn server_update_system(
                         info!("Server is going down...");
                         ev_app_exit.write(AppExit::Success);
                     } else {
+                        // Removed from the lobby along with the other disconnected clients
                         server.disconnect(client_id);
-                        lobby.players.remove(&client_id);
                         info!("Player {:?} disconnected", client_id);
                     }
                 }
@@ -222,6 +372,21 @@ fn server_update_system(
                         ev_save_request.write(SaveRequestEvent::Player(client_id));
                     }
                 }
+                ClientToServerMessage::SetViewDistance(requested) => {
+                    let view_distance = requested.clamp(1, config.max_view_distance.max(1));
+                    debug!(
+                        "Player {} asked for a view distance of {}, granted {}",
+                        client_id, requested, view_distance
+                    );
+
+                    views.get_or_default(client_id).view_distance = view_distance;
+                    server.send_game_message(
+                        client_id,
+                        ServerToClientMessage::ViewDistance(view_distance),
+                    );
+                }
+                // Only there to refresh the activity of the client
+                ClientToServerMessage::Heartbeat => {}
             }
         }
     }
@@ -0,0 +1,112 @@
+//! Kicks the clients the server stopped hearing from.
+//!
+//! Clients send a heartbeat every few seconds on top of their other messages,
+//! so that a frozen client is told apart from a player standing still.
+
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+use shared::messages::ChatConversation;
+use shared::world::ServerWorldMap;
+use shared::{GameServerConfig, TICKS_PER_SECOND};
+use std::collections::HashMap;
+
+use crate::init::{ServerLobby, ServerTime};
+use crate::world::save::SaveRequestEvent;
+use crate::world::view::PlayerViews;
+
+use super::broadcast_chat::ChatMessageEvent;
+use super::cleanup::{announce_player_left, cleanup_player_from_world};
+
+struct ClientActivity {
+    connected_tick: u64,
+    /// Tick of the last message received from the client
+    last_seen_tick: u64,
+}
+
+/// Activity of every connected client, authenticated or not
+#[derive(Resource, Default)]
+pub struct ClientActivities {
+    clients: HashMap<ClientId, ClientActivity>,
+}
+
+impl ClientActivities {
+    pub fn connected(&mut self, client_id: ClientId, tick: u64) {
+        self.clients.insert(
+            client_id,
+            ClientActivity {
+                connected_tick: tick,
+                last_seen_tick: tick,
+            },
+        );
+    }
+
+    pub fn seen(&mut self, client_id: ClientId, tick: u64) {
+        if let Some(activity) = self.clients.get_mut(&client_id) {
+            activity.last_seen_tick = tick;
+        }
+    }
+
+    pub fn remove(&mut self, client_id: &ClientId) {
+        self.clients.remove(client_id);
+    }
+}
+
+/// Disconnects the clients which did not authenticate in time, and the players
+/// whose heartbeats stopped. Their data is saved and the other players are told.\
+/// A timeout of 0 never kicks anyone
+pub fn kick_unresponsive_clients_system(
+    mut server: ResMut<RenetServer>,
+    mut activities: ResMut<ClientActivities>,
+    (mut lobby, mut world_map, mut views): (
+        ResMut<ServerLobby>,
+        ResMut<ServerWorldMap>,
+        ResMut<PlayerViews>,
+    ),
+    mut chat_conversation: ResMut<ChatConversation>,
+    mut ev_chat: EventWriter<ChatMessageEvent>,
+    mut ev_save_request: EventWriter<SaveRequestEvent>,
+    config: Res<GameServerConfig>,
+    time: Res<ServerTime>,
+) {
+    let heartbeat_timeout = config.heartbeat_timeout_secs * TICKS_PER_SECOND;
+    let auth_timeout = config.auth_timeout_secs * TICKS_PER_SECOND;
+
+    let mut kicked = Vec::new();
+    activities.clients.retain(|client_id, activity| {
+        let timed_out = if lobby.players.contains_key(client_id) {
+            heartbeat_timeout > 0
+                && time.0.saturating_sub(activity.last_seen_tick) > heartbeat_timeout
+        } else {
+            auth_timeout > 0 && time.0.saturating_sub(activity.connected_tick) > auth_timeout
+        };
+        if timed_out {
+            kicked.push(*client_id);
+        }
+        !timed_out
+    });
+
+    for client_id in kicked {
+        server.disconnect(client_id);
+
+        let Some(player) = lobby.players.remove(&client_id) else {
+            info!(
+                "Client {} kicked, it did not authenticate in time",
+                client_id
+            );
+            continue;
+        };
+
+        warn!(
+            "Player {} ({}) kicked, nothing received for {} seconds",
+            player.name, client_id, config.heartbeat_timeout_secs
+        );
+        cleanup_player_from_world(&mut world_map, &mut views, &client_id, &mut ev_save_request);
+        announce_player_left(
+            &mut server,
+            &mut chat_conversation,
+            &mut ev_chat,
+            client_id,
+            format!("{} timed out", player.name),
+        );
+    }
+}
@@ -1,4 +1,7 @@
+pub mod access;
+pub mod auth;
 pub mod broadcast_chat;
 pub mod cleanup;
 pub mod dispatcher;
 pub mod extensions;
+pub mod heartbeat;
@@ -1,28 +1,147 @@
 use bevy::prelude::*;
-use shared::world::{ServerWorldMap, WorldSeed};
+use bevy::tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task};
+use shared::world::{world_position_to_chunk_position, ServerChunk, ServerWorldMap};
+use shared::GameFolderPaths;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use crate::world::generation::generate_chunk;
+use crate::world::data::get_region_folder_path;
+use crate::world::generation::GeneratedChunk;
+use crate::world::generator::{ActiveWorldGenerator, WorldGenerator};
+use crate::world::region::load_chunk;
+use crate::world::streaming::{insert_generated_chunk, insert_loaded_chunk};
 
-use super::broadcast_world::{get_all_active_chunks, BROADCAST_RENDER_DISTANCE};
+use super::view::{get_all_active_chunks, PlayerViews};
 
+/// Maximum number of chunks loaded or generated at the same time
+const MAX_CHUNK_TASKS: usize = 16;
+
+/// Result of a chunk task, computed away from the tick
+enum ChunkTaskResult {
+    Loaded(ServerChunk),
+    Generated(GeneratedChunk),
+    Failed(String),
+}
+
+/// Chunks being read from the region files or generated on the async compute task pool
+#[derive(Resource, Default)]
+pub struct ChunkGenerationQueue {
+    tasks: HashMap<IVec3, Task<ChunkTaskResult>>,
+    /// Chunks which could not be read from disk, never generated over until the next restart
+    failed: HashSet<IVec3>,
+}
+
+/// Reads a chunk from the region files, or generates it if it was never saved.\
+/// Disk reads happen in the task, so that the tick never waits for them
+fn spawn_chunk_task(
+    generator: Arc<dyn WorldGenerator>,
+    region_folder: PathBuf,
+    chunk_pos: IVec3,
+) -> Task<ChunkTaskResult> {
+    AsyncComputeTaskPool::get().spawn(async move {
+        match load_chunk(&region_folder, &chunk_pos) {
+            Ok(Some(chunk)) => ChunkTaskResult::Loaded(chunk),
+            Ok(None) => {
+                let generated = generator.generate_chunk(chunk_pos);
+                // The pre-generation may have saved it in the meantime, the saved one wins
+                match load_chunk(&region_folder, &chunk_pos) {
+                    Ok(Some(chunk)) => ChunkTaskResult::Loaded(chunk),
+                    Ok(None) => ChunkTaskResult::Generated(generated),
+                    Err(err) => ChunkTaskResult::Failed(err.to_string()),
+                }
+            }
+            Err(err) => ChunkTaskResult::Failed(err.to_string()),
+        }
+    })
+}
+
+/// Loads the chunks around players in the background, closest to a player first.\
+/// Results are merged into the world as soon as they are ready, so that the tick
+/// never waits for the generation
 pub fn background_world_generation_system(
     mut world_map: ResMut<ServerWorldMap>,
-    seed: Res<WorldSeed>,
+    mut queue: ResMut<ChunkGenerationQueue>,
+    generator: Res<ActiveWorldGenerator>,
+    game_folder_paths: Res<GameFolderPaths>,
+    views: Res<PlayerViews>,
 ) {
-    let all_chunks = get_all_active_chunks(&world_map.players, BROADCAST_RENDER_DISTANCE);
-    let mut generated = 0;
-    for c in all_chunks {
-        let chunk = world_map.chunks.map.get(&c);
-
-        if chunk.is_none() {
-            let chunk = generate_chunk(c, seed.0);
-            info!("Generated chunk: {:?}", c);
-            world_map.chunks.map.insert(c, chunk);
-            generated += 1;
+    let world_map = world_map.as_mut();
+    let queue = queue.as_mut();
+    let region_folder = get_region_folder_path(&game_folder_paths, &world_map.name);
+
+    let mut finished = Vec::new();
+    queue
+        .tasks
+        .retain(|chunk_pos, task| match block_on(future::poll_once(task)) {
+            Some(result) => {
+                finished.push((*chunk_pos, result));
+                false
+            }
+            None => true,
+        });
+
+    for (chunk_pos, result) in finished {
+        // The pre-generation may have produced the chunk in the meantime
+        if world_map.chunks.map.contains_key(&chunk_pos) {
+            continue;
         }
 
-        if generated >= 1 {
-            break;
+        match result {
+            ChunkTaskResult::Loaded(chunk) => {
+                debug!("Loaded chunk from disk: {:?}", chunk_pos);
+                insert_loaded_chunk(&mut world_map.chunks, chunk_pos, chunk);
+            }
+            ChunkTaskResult::Generated(generated) => {
+                info!("Generated chunk: {:?}", chunk_pos);
+                insert_generated_chunk(&mut world_map.chunks, chunk_pos, generated);
+            }
+            ChunkTaskResult::Failed(err) => {
+                // Never generate over a chunk that exists on disk but could not be read
+                error!("Could not load chunk {:?} from disk : {}", chunk_pos, err);
+                queue.failed.insert(chunk_pos);
+            }
         }
     }
+
+    if queue.tasks.len() >= MAX_CHUNK_TASKS {
+        return;
+    }
+
+    let player_chunks: Vec<IVec3> = world_map
+        .players
+        .values()
+        .map(|player| world_position_to_chunk_position(player.position))
+        .collect();
+
+    let mut requested: Vec<IVec3> = get_all_active_chunks(&world_map.players, &views)
+        .into_iter()
+        .filter(|chunk_pos| {
+            !world_map.chunks.map.contains_key(chunk_pos)
+                && !queue.tasks.contains_key(chunk_pos)
+                && !queue.failed.contains(chunk_pos)
+        })
+        .collect();
+
+    requested.sort_by_key(|chunk_pos| {
+        player_chunks
+            .iter()
+            .map(|player_chunk| (*chunk_pos - *player_chunk).length_squared())
+            .min()
+            .unwrap_or_default()
+    });
+
+    for chunk_pos in requested
+        .into_iter()
+        .take(MAX_CHUNK_TASKS - queue.tasks.len())
+    {
+        queue.tasks.insert(
+            chunk_pos,
+            spawn_chunk_task(
+                Arc::clone(&generator.generator),
+                region_folder.clone(),
+                chunk_pos,
+            ),
+        );
+    }
 }
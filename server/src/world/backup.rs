@@ -0,0 +1,261 @@
+use bevy::prelude::*;
+use bevy::tasks::{IoTaskPool, Task};
+use shared::world::ServerWorldMap;
+use shared::{GameFolderPaths, GameServerConfig, TICKS_PER_SECOND};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::init::ServerTime;
+use crate::world::data::{get_backups_folder_path, get_world_folder_path};
+use crate::world::region::{copy_region_file, is_region_file};
+
+/// Extension of folders which are still being written
+const TMP_EXTENSION: &str = "tmp";
+
+#[derive(Debug, Clone)]
+pub struct BackupInfo {
+    /// Name of the backup folder, which is its creation date as a UNIX timestamp
+    pub name: String,
+    pub path: PathBuf,
+    pub created_at: u64,
+}
+
+/// Backup being made in the background
+#[derive(Resource, Default)]
+pub struct PendingBackup(Option<Task<()>>);
+
+/// Appends `.tmp` to a path, without replacing its extension like `Path::with_extension` would
+fn tmp_path_for(path: &Path) -> PathBuf {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".");
+    tmp_path.push(TMP_EXTENSION);
+    PathBuf::from(tmp_path)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Copies a whole directory, skipping temporary files left by interrupted writes.\
+/// Region files are copied so that chunks can still be saved to them in the meantime
+fn copy_dir_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path.extension().is_some_and(|ext| ext == TMP_EXTENSION) {
+            continue;
+        }
+
+        if entry.file_type()?.is_dir() {
+            copy_dir_recursive(&path, &to.join(entry.file_name()))?;
+        } else if is_region_file(&path) {
+            copy_region_file(&path, &to.join(entry.file_name()))?;
+        } else {
+            fs::copy(&path, to.join(entry.file_name()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the backups of a world, newest first
+pub fn list_backups(
+    game_folder_paths: &GameFolderPaths,
+    world_name: &str,
+) -> io::Result<Vec<BackupInfo>> {
+    let backups_folder = get_backups_folder_path(game_folder_paths, world_name);
+    if !backups_folder.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups: Vec<BackupInfo> = fs::read_dir(&backups_folder)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let created_at = name.parse::<u64>().ok()?;
+            Some(BackupInfo {
+                name,
+                path: entry.path(),
+                created_at,
+            })
+        })
+        .collect();
+
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+
+    Ok(backups)
+}
+
+/// Copies the current save of a world into a new timestamped backup.\
+/// Returns `None` if the world has never been saved
+pub fn create_backup(
+    game_folder_paths: &GameFolderPaths,
+    world_name: &str,
+) -> io::Result<Option<BackupInfo>> {
+    let world_folder = get_world_folder_path(game_folder_paths, world_name);
+    if !world_folder.exists() {
+        return Ok(None);
+    }
+
+    let created_at = now_secs();
+    let name = created_at.to_string();
+    let path = get_backups_folder_path(game_folder_paths, world_name).join(&name);
+
+    // Several saves in the same second share the same backup
+    if path.exists() {
+        return Ok(None);
+    }
+
+    // Copy to a temporary folder first, so that an interrupted backup is never listed
+    let tmp_path = tmp_path_for(&path);
+    if tmp_path.exists() {
+        fs::remove_dir_all(&tmp_path)?;
+    }
+
+    // Chunks keep being loaded and saved during the copy
+    copy_dir_recursive(&world_folder, &tmp_path)?;
+    fs::rename(&tmp_path, &path)?;
+
+    Ok(Some(BackupInfo {
+        name,
+        path,
+        created_at,
+    }))
+}
+
+/// Deletes the backups exceeding `max_count`, as well as those older than `max_age_secs`
+/// (0 means no age limit). The most recent backup is always kept.
+pub fn prune_backups(
+    game_folder_paths: &GameFolderPaths,
+    world_name: &str,
+    max_count: u32,
+    max_age_secs: u64,
+) -> io::Result<()> {
+    let now = now_secs();
+
+    for (i, backup) in list_backups(game_folder_paths, world_name)?
+        .iter()
+        .enumerate()
+    {
+        if i == 0 {
+            continue;
+        }
+
+        let too_many = i >= max_count as usize;
+        let too_old = max_age_secs > 0 && now.saturating_sub(backup.created_at) > max_age_secs;
+
+        if too_many || too_old {
+            fs::remove_dir_all(&backup.path)?;
+            info!("[{}] Deleted backup {}", world_name, backup.name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the save of a world with one of its backups.\
+/// The current save is itself backed up first, so that a restore can be undone.\
+/// Must not be called while a server is running on this world.
+pub fn restore_backup(
+    game_folder_paths: &GameFolderPaths,
+    world_name: &str,
+    backup_name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backup = list_backups(game_folder_paths, world_name)?
+        .into_iter()
+        .find(|backup| backup.name == backup_name)
+        .ok_or_else(|| format!("No backup named {backup_name} for world {world_name}"))?;
+
+    create_backup(game_folder_paths, world_name)?;
+
+    let world_folder = get_world_folder_path(game_folder_paths, world_name);
+    let tmp_path = tmp_path_for(&world_folder);
+    if tmp_path.exists() {
+        fs::remove_dir_all(&tmp_path)?;
+    }
+
+    copy_dir_recursive(&backup.path, &tmp_path)?;
+
+    if world_folder.exists() {
+        fs::remove_dir_all(&world_folder)?;
+    }
+    fs::rename(&tmp_path, &world_folder)?;
+
+    Ok(())
+}
+
+/// Human readable duration since a backup was made, e.g. `3h 12m`
+pub fn format_backup_age(backup: &BackupInfo) -> String {
+    let age = now_secs().saturating_sub(backup.created_at);
+    let (days, hours, minutes) = (age / 86400, (age % 86400) / 3600, (age % 3600) / 60);
+
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else {
+        format!("{minutes}m {}s", age % 60)
+    }
+}
+
+/// Backs up the world on its own interval, on the IO task pool.\
+/// Region files are copied while chunks keep being written, so saves never wait for a backup
+pub fn backup_system(
+    time: Res<ServerTime>,
+    config: Res<GameServerConfig>,
+    world_map: Res<ServerWorldMap>,
+    game_folder_paths: Res<GameFolderPaths>,
+    mut pending_backup: ResMut<PendingBackup>,
+) {
+    if config.backup_interval_secs == 0 || config.backup_count == 0 || time.0 == 0 {
+        return;
+    }
+
+    if !time
+        .0
+        .is_multiple_of(config.backup_interval_secs * TICKS_PER_SECOND)
+    {
+        return;
+    }
+
+    if pending_backup
+        .0
+        .as_ref()
+        .is_some_and(|task| !task.is_finished())
+    {
+        warn!(
+            "[{}] Skipping backup, the previous one is still running",
+            world_map.name
+        );
+        return;
+    }
+
+    let world_name = world_map.name.clone();
+    let game_folder_paths = game_folder_paths.clone();
+    let (backup_count, backup_max_age_secs) = (config.backup_count, config.backup_max_age_secs);
+
+    pending_backup.0 = Some(IoTaskPool::get().spawn(async move {
+        match create_backup(&game_folder_paths, &world_name) {
+            Ok(Some(backup)) => info!("[{}] Created backup {}", world_name, backup.name),
+            Ok(None) => {}
+            Err(err) => error!("[{}] Could not create backup : {}", world_name, err),
+        }
+
+        if let Err(err) = prune_backups(
+            &game_folder_paths,
+            &world_name,
+            backup_count,
+            backup_max_age_secs,
+        ) {
+            error!("[{}] Could not delete old backups : {}", world_name, err);
+        }
+    }));
+}
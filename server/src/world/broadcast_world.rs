@@ -1,24 +1,28 @@
 use crate::init::ServerTime;
 use crate::network::extensions::SendGameMessageExtension;
+use crate::world::view::{PlayerView, PlayerViews};
 use bevy::math::IVec3;
 use bevy::prelude::*;
 use bevy_ecs::system::ResMut;
 use bevy_renet::renet::RenetServer;
 use shared::messages::mob::MobUpdateEvent;
-use shared::messages::{ItemStackUpdateEvent, PlayerId, ServerToClientMessage, WorldUpdate};
-use shared::players::Player;
+use shared::messages::{
+    BlockUpdate, ChunkUnload, ItemStackUpdateEvent, ServerToClientMessage, WorldUpdate,
+};
 use shared::world::{
-    world_position_to_chunk_position, ServerChunk, ServerChunkWorldMap, ServerWorldMap,
+    global_block_to_chunk_pos, world_position_to_chunk_position, BlockData, MobId, ServerChunk,
+    ServerChunkWorldMap, ServerMob, ServerWorldMap, WorldMap,
 };
-use shared::CHUNK_SIZE;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-pub const BROADCAST_RENDER_DISTANCE: i32 = 1;
+/// Maximum number of chunks sent to a client in a single update, closest first
+const MAX_CHUNKS_PER_UPDATE: usize = 16;
 
 pub fn broadcast_world_state(
     mut server: ResMut<RenetServer>,
     time: Res<ServerTime>,
     mut world_map: ResMut<ServerWorldMap>,
+    mut views: ResMut<PlayerViews>,
 ) {
     let ts = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -27,10 +31,12 @@ pub fn broadcast_world_state(
 
     let world_map = world_map.as_mut();
 
-    let mobs = world_map.mobs.clone();
+    let mobs = &world_map.mobs;
     let players = &mut world_map.players;
     let chunks = &mut world_map.chunks;
 
+    let block_updates = take_block_updates(chunks);
+
     for client in server.clients_id().iter_mut() {
         let player = players.get_mut(client);
         let player = match player {
@@ -38,25 +44,66 @@ pub fn broadcast_world_state(
             None => continue,
         };
 
-        for (id, mob) in mobs.iter() {
-            if mob.position.distance(player.position)
-                < (BROADCAST_RENDER_DISTANCE * CHUNK_SIZE) as f32
-            {
-                server.send_game_message(
-                    *client,
-                    ServerToClientMessage::MobUpdate(MobUpdateEvent {
-                        id: *id,
-                        mob: mob.clone(),
-                    }),
-                );
-            }
+        let view = views.get_or_default(player.id);
+        let player_chunk = world_position_to_chunk_position(player.position);
+
+        // Mobs out of view are never sent, the client despawns them on its side
+        let visible_mobs: HashMap<MobId, ServerMob> = mobs
+            .iter()
+            .filter(|(_, mob)| view.can_see(player.position, mob.position))
+            .map(|(id, mob)| (*id, mob.clone()))
+            .collect();
+
+        for (id, mob) in visible_mobs.iter() {
+            server.send_game_message(
+                *client,
+                ServerToClientMessage::MobUpdate(MobUpdateEvent {
+                    id: *id,
+                    mob: mob.clone(),
+                }),
+            );
+        }
+
+        let unloaded_chunks = unload_out_of_range_chunks(view, player_chunk);
+        if !unloaded_chunks.is_empty() {
+            debug!(
+                "Unloading {} chunks from player {}",
+                unloaded_chunks.len(),
+                player.id
+            );
+            server.send_game_message(
+                *client,
+                ServerToClientMessage::ChunkUnload(ChunkUnload {
+                    chunks: unloaded_chunks,
+                }),
+            );
+        }
+
+        // Sent before the new chunks, which already contain the changes
+        let player_block_updates: Vec<(IVec3, Option<BlockData>)> = block_updates
+            .iter()
+            .filter(|(block_pos, _)| {
+                view.loaded_chunks
+                    .contains(&global_block_to_chunk_pos(block_pos))
+            })
+            .copied()
+            .collect();
+
+        if !player_block_updates.is_empty() {
+            server.send_game_message(
+                *client,
+                ServerToClientMessage::BlockUpdate(BlockUpdate {
+                    tick: time.0,
+                    blocks: player_block_updates,
+                }),
+            );
         }
 
         let msg = WorldUpdate {
             tick: time.0,
             time: ts,
-            new_map: get_world_map_chunks_to_send(chunks, players, &player),
-            mobs: mobs.clone(),
+            new_map: get_world_map_chunks_to_send(chunks, view, player_chunk),
+            mobs: visible_mobs,
             item_stacks: get_items_stacks(),
         };
 
@@ -68,46 +115,60 @@ pub fn broadcast_world_state(
 
         server.send_game_message(*client, message);
     }
+}
 
-    // Clear the list of chunks that needed updates after broadcasting to all clients
-    chunks.chunks_to_update.clear();
+/// Current state of every block changed since the last broadcast, each block only once
+fn take_block_updates(chunks: &mut ServerChunkWorldMap) -> Vec<(IVec3, Option<BlockData>)> {
+    let mut seen = HashSet::new();
+    let updated_blocks = std::mem::take(&mut chunks.updated_blocks);
+
+    updated_blocks
+        .into_iter()
+        .filter(|block_pos| seen.insert(*block_pos))
+        .map(|block_pos| {
+            let block = chunks.get_block_by_coordinates(&block_pos).copied();
+            (block_pos, block)
+        })
+        .collect()
+}
+
+/// Forgets the chunks the player moved away from, returning them so that the client frees them too
+fn unload_out_of_range_chunks(view: &mut PlayerView, player_chunk: IVec3) -> Vec<IVec3> {
+    let unloaded_chunks: Vec<IVec3> = view
+        .loaded_chunks
+        .iter()
+        .filter(|chunk_pos| view.is_out_of_range(player_chunk, **chunk_pos))
+        .copied()
+        .collect();
+
+    for chunk_pos in unloaded_chunks.iter() {
+        view.loaded_chunks.remove(chunk_pos);
+    }
+
+    unloaded_chunks
 }
 
 fn get_world_map_chunks_to_send(
-    chunks: &mut ServerChunkWorldMap,
-    players: &HashMap<PlayerId, Player>,
-    player: &Player,
+    chunks: &ServerChunkWorldMap,
+    view: &mut PlayerView,
+    player_chunk: IVec3,
 ) -> HashMap<IVec3, ServerChunk> {
     // Send only chunks in render distance
     let mut map: HashMap<IVec3, ServerChunk> = HashMap::new();
 
-    let active_chunks = get_all_active_chunks(players, BROADCAST_RENDER_DISTANCE);
-
-    // First, handle chunks that need to be updated (re-sent due to modifications)
-    for &chunk_pos in &chunks.chunks_to_update {
-        if active_chunks.contains(&chunk_pos) {
-            if let Some(chunk) = chunks.map.get_mut(&chunk_pos) {
-                // Clear sent_to_clients list so the chunk will be re-sent to all players
-                chunk.sent_to_clients.clear();
-            }
-        }
-    }
-
-    for c in active_chunks {
-        if map.len() >= 10 {
+    for c in view.chunks_in_range(player_chunk) {
+        if map.len() >= MAX_CHUNKS_PER_UPDATE {
             break;
         }
 
-        let chunk = chunks.map.get_mut(&c);
-
-        // If chunk already exists, transmit it to client
-        if let Some(chunk) = chunk {
-            if chunk.sent_to_clients.contains(&player.id) {
-                continue;
-            }
+        if view.loaded_chunks.contains(&c) {
+            continue;
+        }
 
+        // Chunks still being loaded or generated are sent on a later update
+        if let Some(chunk) = chunks.map.get(&c) {
             map.insert(c, chunk.clone());
-            chunk.sent_to_clients.push(player.id);
+            view.loaded_chunks.insert(c);
         }
     }
 
@@ -130,40 +191,3 @@ fn get_items_stacks() -> Vec<ItemStackUpdateEvent> {
     //     })
     //     .collect()
 }
-
-pub fn get_all_active_chunks(players: &HashMap<PlayerId, Player>, radius: i32) -> Vec<IVec3> {
-    let player_chunks: Vec<IVec3> = players
-        .values()
-        .map(|v| world_position_to_chunk_position(v.position))
-        .flat_map(|v| get_player_nearby_chunks_coords(v, radius))
-        .collect();
-
-    let mut chunks: Vec<IVec3> = Vec::new();
-
-    for c in player_chunks {
-        if !chunks.contains(&c) {
-            chunks.push(c);
-        }
-    }
-
-    chunks
-}
-
-fn get_player_nearby_chunks_coords(
-    player_chunk_position: IVec3,
-    render_distance: i32,
-) -> Vec<IVec3> {
-    let mut chunks: Vec<IVec3> = Vec::new();
-    for x in -render_distance..=render_distance {
-        for y in -render_distance..=render_distance {
-            for z in -render_distance..=render_distance {
-                chunks.push(player_chunk_position + IVec3::new(x, y, z));
-            }
-        }
-    }
-
-    // let's sort by distance to player
-    chunks.sort_by_key(|&c| (c - player_chunk_position).length_squared());
-
-    chunks
-}
@@ -0,0 +1,135 @@
+use noise::{NoiseFn, Perlin};
+
+/// Water fills every column up to this height
+pub const SEA_LEVEL: i32 = 62;
+
+/// Blocks at or below this height are never carved, so that caves can't open into the void
+const MIN_CARVE_HEIGHT: i32 = 4;
+
+/// Caves stay this far below the floor of underwater columns, so that they never breach the sea
+const UNDERWATER_CAVE_ROOF: i32 = 8;
+
+/// Caverns open where the cheese noise goes above this value
+const CHEESE_THRESHOLD: f64 = 0.55;
+
+/// Tunnels follow the places where both spaghetti noises are closer to 0 than this value
+const SPAGHETTI_WIDTH: f64 = 0.07;
+
+/// Ravines follow the places where the ravine noise is closer to 0 than this value
+const RAVINE_HALF_WIDTH: f64 = 0.012;
+const RAVINE_MAX_DEPTH: f64 = 30.;
+/// Only the parts of the ravine paths where the mask noise goes above this value are dug
+const RAVINE_MASK_THRESHOLD: f64 = 0.35;
+
+/// Maximum number of blocks the surface of mountains is moved up or down to shape overhangs
+pub const OVERHANG_AMPLITUDE: i32 = 6;
+
+/// 3D noises shaping the terrain below and around the heightmap : caves, ravines and overhangs.
+///
+/// Everything only depends on the world seed and the block position.\
+/// Noises are only sampled for blocks which would be solid otherwise,
+/// so that chunks of air or water cost nothing more to generate.
+pub struct TerrainCarver {
+    cheese: Perlin,
+    spaghetti_a: Perlin,
+    spaghetti_b: Perlin,
+    ravine: Perlin,
+    overhang: Perlin,
+}
+
+impl TerrainCarver {
+    pub fn new(seed: u32) -> Self {
+        // The first seeds are used by the heightmap and the biomes
+        Self {
+            cheese: Perlin::new(seed.wrapping_add(3)),
+            spaghetti_a: Perlin::new(seed.wrapping_add(4)),
+            spaghetti_b: Perlin::new(seed.wrapping_add(5)),
+            ravine: Perlin::new(seed.wrapping_add(6)),
+            overhang: Perlin::new(seed.wrapping_add(7)),
+        }
+    }
+
+    fn is_underwater(terrain_height: i32) -> bool {
+        terrain_height <= SEA_LEVEL
+    }
+
+    /// Height of the bottom of the ravine crossing a column, if any
+    pub fn ravine_floor(&self, x: i32, z: i32, terrain_height: i32) -> Option<i32> {
+        if Self::is_underwater(terrain_height) {
+            return None;
+        }
+
+        let (fx, fz) = (x as f64, z as f64);
+        let path = self.ravine.get([fx * 0.004, fz * 0.004]).abs();
+        if path >= RAVINE_HALF_WIDTH {
+            return None;
+        }
+
+        let mask = self.ravine.get([fx * 0.002 + 512., fz * 0.002 + 512.]);
+        if mask < RAVINE_MASK_THRESHOLD {
+            return None;
+        }
+
+        // Deepest in the middle of the path, with steep walls
+        let depth = RAVINE_MAX_DEPTH * (1. - path / RAVINE_HALF_WIDTH).sqrt();
+        Some((terrain_height - depth as i32).max(MIN_CARVE_HEIGHT + 1))
+    }
+
+    /// Height of the surface of a column at a given height.\
+    /// Without overhangs, this is the heightmap. In mountains, it varies with the height,
+    /// so that some blocks end up above air
+    pub fn surface_height(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        terrain_height: i32,
+        overhangs: bool,
+    ) -> f64 {
+        if !overhangs {
+            return terrain_height as f64;
+        }
+
+        let offset = self
+            .overhang
+            .get([x as f64 * 0.06, y as f64 * 0.08, z as f64 * 0.06]);
+        terrain_height as f64 + offset * OVERHANG_AMPLITUDE as f64
+    }
+
+    /// Whether a block below the surface is dug out by a cave or a ravine
+    pub fn is_carved(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        terrain_height: i32,
+        ravine_floor: Option<i32>,
+    ) -> bool {
+        if y <= MIN_CARVE_HEIGHT {
+            return false;
+        }
+
+        if Self::is_underwater(terrain_height) && y > terrain_height - UNDERWATER_CAVE_ROOF {
+            return false;
+        }
+
+        if ravine_floor.is_some_and(|floor| y >= floor) {
+            return true;
+        }
+
+        let (fx, fy, fz) = (x as f64, y as f64, z as f64);
+
+        // Large open caverns
+        if self.cheese.get([fx * 0.02, fy * 0.035, fz * 0.02]) > CHEESE_THRESHOLD {
+            return true;
+        }
+
+        // Long winding tunnels
+        let a = self.spaghetti_a.get([fx * 0.03, fy * 0.05, fz * 0.03]);
+        if a.abs() >= SPAGHETTI_WIDTH {
+            return false;
+        }
+        let b = self.spaghetti_b.get([fx * 0.03, fy * 0.05, fz * 0.03]);
+        b.abs() < SPAGHETTI_WIDTH
+    }
+}
@@ -1 +1,48 @@
+use shared::GameFolderPaths;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+
 pub const SAVE_PATH: &str = "saves/";
+pub const REGION_FOLDER: &str = "region/";
+pub const PLAYERS_FOLDER: &str = "players/";
+pub const BACKUP_PATH: &str = "backups/";
+pub const CUSTOM_BIOMES_PATH: &str = "biomes/";
+
+pub fn get_world_folder_path(game_folder_paths: &GameFolderPaths, world_name: &str) -> PathBuf {
+    game_folder_paths
+        .game_folder_path
+        .join(SAVE_PATH)
+        .join(world_name)
+}
+
+pub fn get_region_folder_path(game_folder_paths: &GameFolderPaths, world_name: &str) -> PathBuf {
+    get_world_folder_path(game_folder_paths, world_name).join(REGION_FOLDER)
+}
+
+/// Player saves are named after the username rather than the client id,
+/// which changes on every connection for servers without connect tokens.\
+/// The hash is the one clients used as their id before, so that older saves are still found
+pub fn get_player_save_path(
+    game_folder_paths: &GameFolderPaths,
+    world_name: &str,
+    username: &str,
+) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    username.hash(&mut hasher);
+
+    get_world_folder_path(game_folder_paths, world_name)
+        .join(PLAYERS_FOLDER)
+        .join(format!("{}.ron", hasher.finish()))
+}
+
+pub fn get_backups_folder_path(game_folder_paths: &GameFolderPaths, world_name: &str) -> PathBuf {
+    game_folder_paths
+        .game_folder_path
+        .join(BACKUP_PATH)
+        .join(world_name)
+}
+
+/// Biome files added to the server, on top of the builtin biomes
+pub fn get_custom_biomes_folder_path(game_folder_paths: &GameFolderPaths) -> PathBuf {
+    game_folder_paths.game_folder_path.join(CUSTOM_BIOMES_PATH)
+}
@@ -1,13 +1,61 @@
 use bevy::prelude::*;
 use noise::{NoiseFn, Perlin};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use shared::{world::*, CHUNK_SIZE};
-use std::collections::HashMap;
 
-fn generate_tree(chunk: &mut ServerChunk, x: i32, y: i32, z: i32, trunk: BlockId, leaves: BlockId) {
+use super::carving::{TerrainCarver, OVERHANG_AMPLITUDE, SEA_LEVEL};
+use super::ores::generate_ores;
+use super::rivers::WaterShaper;
+
+/// Result of the generation of a single chunk
+pub struct GeneratedChunk {
+    pub chunk: ServerChunk,
+    /// Blocks of structures (e.g. trees) crossing the borders of the chunk,
+    /// which belong to the neighbouring chunks
+    pub spilled_blocks: PendingBlocks,
+}
+
+/// Chunk being generated. Positions are local to the chunk,
+/// and blocks placed outside of it are set aside for the neighbouring chunks
+struct ChunkBuilder {
+    chunk_pos: IVec3,
+    chunk: ServerChunk,
+    spilled_blocks: PendingBlocks,
+}
+
+impl ChunkBuilder {
+    fn insert(&mut self, local_pos: IVec3, block: BlockData) {
+        if local_pos.cmpge(IVec3::ZERO).all() && local_pos.cmplt(IVec3::splat(CHUNK_SIZE)).all() {
+            self.chunk.blocks.insert(local_pos, block);
+            return;
+        }
+
+        let global_pos = to_global_pos(&self.chunk_pos, &local_pos);
+        self.spilled_blocks
+            .entry(global_block_to_chunk_pos(&global_pos))
+            .or_default()
+            .push((to_local_pos(&global_pos), block));
+    }
+
+    fn contains(&self, local_pos: &IVec3) -> bool {
+        self.chunk.blocks.contains_key(local_pos)
+    }
+}
+
+fn generate_tree(
+    chunk: &mut ChunkBuilder,
+    rng: &mut impl Rng,
+    x: i32,
+    y: i32,
+    z: i32,
+    trunk: BlockId,
+    leaves: BlockId,
+) {
     // create trunk
-    let trunk_height = 3 + rand::random::<u8>() % 3; // random height between 3 and 5
+    let trunk_height = 3 + rng.gen::<u8>() % 3; // random height between 3 and 5
     for dy in 0..trunk_height {
-        chunk.map.insert(
+        chunk.insert(
             IVec3::new(x, y + dy as i32, z),
             BlockData::new(trunk, BlockDirection::Front),
         );
@@ -21,10 +69,10 @@ fn generate_tree(chunk: &mut ServerChunk, x: i32, y: i32, z: i32, trunk: BlockId
             for offset_z in -2i32..=2i32 {
                 let cond1 = (offset_x.abs() + offset_z.abs()) < 3 - layer;
                 let cond2 = (offset_x.abs() + offset_z.abs()) == 3 - layer
-                    && rand::random::<f32>() < 0.2
+                    && rng.gen::<f32>() < 0.2
                     && layer < 2;
                 if cond1 || cond2 {
-                    chunk.map.insert(
+                    chunk.insert(
                         IVec3::new(x + offset_x, current_y, z + offset_z),
                         BlockData::new(leaves, BlockDirection::Front),
                     );
@@ -32,7 +80,7 @@ fn generate_tree(chunk: &mut ServerChunk, x: i32, y: i32, z: i32, trunk: BlockId
             }
         }
     }
-    chunk.map.insert(
+    chunk.insert(
         IVec3::new(x, y + trunk_height as i32 - 1, z),
         BlockData::new(trunk, BlockDirection::Front),
     );
@@ -41,41 +89,42 @@ fn generate_tree(chunk: &mut ServerChunk, x: i32, y: i32, z: i32, trunk: BlockId
 }
 
 fn generate_big_tree(
-    chunk: &mut ServerChunk,
+    chunk: &mut ChunkBuilder,
+    rng: &mut impl Rng,
     x: i32,
     y: i32,
     z: i32,
     trunk: BlockId,
     leaves: BlockId,
 ) {
-    let trunk_height = 4 + rand::random::<u8>() % 3; // random height between 4 and 7
+    let trunk_height = 4 + rng.gen::<u8>() % 3; // random height between 4 and 7
     let leaf_start_y = y + trunk_height as i32 - 2;
     // add branches
     for _ in 1..3 {
-        let branch_x = x + rand::random::<i32>() % 2;
-        let branch_z = z + rand::random::<i32>() % 2;
-        let branch_y = std::cmp::max(leaf_start_y - 1 - rand::random::<i32>() % 2, 2);
-        let prof = rand::random::<u8>() % 2 + 1;
+        let branch_x = x + rng.gen::<i32>() % 2;
+        let branch_z = z + rng.gen::<i32>() % 2;
+        let branch_y = std::cmp::max(leaf_start_y - 1 - rng.gen::<i32>() % 2, 2);
+        let prof = rng.gen::<u8>() % 2 + 1;
         for dx in 0..prof {
-            chunk.map.insert(
+            chunk.insert(
                 IVec3::new(branch_x + dx as i32, branch_y, branch_z + 1),
                 BlockData::new(leaves, BlockDirection::Front),
             );
-            chunk.map.insert(
+            chunk.insert(
                 IVec3::new(branch_x + dx as i32, branch_y, branch_z - 1),
                 BlockData::new(leaves, BlockDirection::Front),
             );
-            chunk.map.insert(
+            chunk.insert(
                 IVec3::new(branch_x + dx as i32, branch_y + 1, branch_z),
                 BlockData::new(leaves, BlockDirection::Front),
             );
 
-            chunk.map.insert(
+            chunk.insert(
                 IVec3::new(branch_x + dx as i32, branch_y, branch_z),
                 BlockData::new(trunk, BlockDirection::Front),
             );
         }
-        chunk.map.insert(
+        chunk.insert(
             IVec3::new(branch_x + prof as i32, branch_y, branch_z),
             BlockData::new(leaves, BlockDirection::Front),
         );
@@ -83,7 +132,7 @@ fn generate_big_tree(
     // create trunk
 
     for dy in 0..trunk_height {
-        chunk.map.insert(
+        chunk.insert(
             IVec3::new(x, y + dy as i32, z),
             BlockData::new(trunk, BlockDirection::Front),
         );
@@ -96,7 +145,7 @@ fn generate_big_tree(
         for offset_x in -2i32..=2i32 {
             for offset_z in -2i32..=2i32 {
                 if !(offset_x == 0 && offset_z == 0 || offset_x.abs() == 2 && offset_z.abs() == 2) {
-                    chunk.map.insert(
+                    chunk.insert(
                         IVec3::new(x + offset_x, current_y, z + offset_z),
                         BlockData::new(leaves, BlockDirection::Front),
                     );
@@ -106,7 +155,7 @@ fn generate_big_tree(
     }
 
     // add one leaf block at the top of the trunk
-    chunk.map.insert(
+    chunk.insert(
         IVec3::new(x, leaf_start_y + 2, z),
         BlockData::new(leaves, BlockDirection::Front),
     );
@@ -118,10 +167,10 @@ fn generate_big_tree(
             for offset_z in -2i32..=2i32 {
                 let cond1 = (offset_x.abs() + offset_z.abs()) < 3 - layer;
                 let cond2 = (offset_x.abs() + offset_z.abs()) == 3 - layer
-                    && rand::random::<f32>() < 0.2
+                    && rng.gen::<f32>() < 0.2
                     && layer < 2;
                 if cond1 || cond2 {
-                    chunk.map.insert(
+                    chunk.insert(
                         IVec3::new(x + offset_x, current_y, z + offset_z),
                         BlockData::new(leaves, BlockDirection::Front),
                     );
@@ -131,52 +180,23 @@ fn generate_big_tree(
     }
 }
 
-fn generate_cactus(chunk: &mut ServerChunk, x: i32, y: i32, z: i32, cactus: BlockId) {
-    let cactus_height = 2 + rand::random::<u8>() % 2;
+fn generate_cactus(
+    chunk: &mut ChunkBuilder,
+    rng: &mut impl Rng,
+    x: i32,
+    y: i32,
+    z: i32,
+    cactus: BlockId,
+) {
+    let cactus_height = 2 + rng.gen::<u8>() % 2;
     for dy in 0..cactus_height {
-        chunk.map.insert(
+        chunk.insert(
             IVec3::new(x, y + dy as i32, z),
             BlockData::new(cactus, BlockDirection::Front),
         );
     }
 }
 
-pub fn determine_biome(temperature: f64, humidity: f64) -> BiomeType {
-    let ocean_percentage: f64 = 0.33;
-    if humidity > (1.0 - (ocean_percentage / 3.0)) {
-        return BiomeType::DeepOcean;
-    }
-    if humidity > (1.0 - 2.0 * (ocean_percentage / 3.0)) {
-        return BiomeType::Ocean;
-    }
-    if humidity > (1.0 - ocean_percentage) {
-        return BiomeType::ShallowOcean;
-    }
-    if temperature > 0.6 {
-        if humidity > (1.0 - ocean_percentage) / 2.0 {
-            BiomeType::Forest
-        } else {
-            BiomeType::Desert
-        }
-    } else if temperature > 0.3 {
-        if humidity > 2.0 * (1.0 - ocean_percentage) / 3.0 {
-            BiomeType::FlowerPlains
-        } else if humidity > (1.0 - ocean_percentage) / 3.0 {
-            BiomeType::Plains
-        } else {
-            BiomeType::MediumMountain
-        }
-    } else if temperature >= 0.0 {
-        if humidity > (1.0 - ocean_percentage) / 2.0 {
-            BiomeType::IcePlain
-        } else {
-            BiomeType::HighMountainGrass
-        }
-    } else {
-        panic!();
-    }
-}
-
 fn interpolated_height(
     x: i32,
     z: i32,
@@ -185,14 +205,14 @@ fn interpolated_height(
     temp_perlin: &Perlin,
     humidity_perlin: &Perlin,
     scale: f64,
+    biomes: &BiomeRegistry,
 ) -> i32 {
     // get the properties of the main biome at (x, z)
     let temperature =
         (temp_perlin.get([x as f64 * biome_scale, z as f64 * biome_scale]) + 1.0) / 2.0;
     let humidity =
         (humidity_perlin.get([x as f64 * biome_scale, z as f64 * biome_scale]) + 1.0) / 2.0;
-    let biome_type = determine_biome(temperature, humidity);
-    let biome = get_biome_data(biome_type);
+    let biome = biomes.find(temperature, humidity);
 
     // initialize weighted values
     let mut weighted_base_height = biome.base_height as f64;
@@ -222,8 +242,7 @@ fn interpolated_height(
                 / 2.0;
 
             // determine the biome of the neighboring block
-            let neighbor_biome_type = determine_biome(neighbor_temp, neighbor_humidity);
-            let neighbor_biome = get_biome_data(neighbor_biome_type);
+            let neighbor_biome = biomes.find(neighbor_temp, neighbor_humidity);
 
             // weight by distance (the farther a neighbor is, the less influence it has)
             let distance = ((offset_x.pow(2) + offset_z.pow(2)) as f64).sqrt();
@@ -247,10 +266,49 @@ fn interpolated_height(
     interpolated_height.round() as i32
 }
 
-pub fn generate_chunk(chunk_pos: IVec3, seed: u32) -> ServerChunk {
+fn generate_tree_kind(
+    chunk: &mut ChunkBuilder,
+    rng: &mut impl Rng,
+    x: i32,
+    y: i32,
+    z: i32,
+    kind: &TreeKind,
+) {
+    match *kind {
+        TreeKind::Tree { trunk, leaves } => generate_tree(chunk, rng, x, y, z, trunk, leaves),
+        TreeKind::BigTree { trunk, leaves } => {
+            generate_big_tree(chunk, rng, x, y, z, trunk, leaves)
+        }
+        TreeKind::Cactus { block } => generate_cactus(chunk, rng, x, y, z, block),
+    }
+}
+
+/// Random generator for everything placed in a chunk.\
+/// It only depends on the world seed and the chunk position, so that a chunk
+/// is always generated the same way, whatever the order chunks are generated in
+fn chunk_rng(seed: u32, chunk_pos: IVec3) -> ChaCha8Rng {
+    let mut rng_seed = [0u8; 32];
+    rng_seed[0..4].copy_from_slice(&seed.to_le_bytes());
+    rng_seed[4..8].copy_from_slice(&chunk_pos.x.to_le_bytes());
+    rng_seed[8..12].copy_from_slice(&chunk_pos.y.to_le_bytes());
+    rng_seed[12..16].copy_from_slice(&chunk_pos.z.to_le_bytes());
+    ChaCha8Rng::from_seed(rng_seed)
+}
+
+/// Generates a chunk from scratch. The result only depends on the arguments.\
+/// If `forced_biome` is set, every column belongs to this biome instead of following the climate
+pub fn generate_chunk(
+    chunk_pos: IVec3,
+    seed: u32,
+    biomes: &BiomeRegistry,
+    forced_biome: Option<&BiomeDefinition>,
+) -> GeneratedChunk {
     let perlin = Perlin::new(seed);
-    let temp_perlin = Perlin::new(seed + 1);
-    let humidity_perlin = Perlin::new(seed + 2);
+    let temp_perlin = Perlin::new(seed.wrapping_add(1));
+    let humidity_perlin = Perlin::new(seed.wrapping_add(2));
+    let carver = TerrainCarver::new(seed);
+    let water_shaper = WaterShaper::new(seed);
+    let mut rng = chunk_rng(seed, chunk_pos);
 
     let scale = 0.1;
     let biome_scale = 0.01;
@@ -258,13 +316,15 @@ pub fn generate_chunk(chunk_pos: IVec3, seed: u32) -> ServerChunk {
     let cy = chunk_pos.y;
     let cz = chunk_pos.z;
 
-    let mut chunk = ServerChunk {
-        map: HashMap::new(),
-        ts: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64,
-        sent_to_clients: vec![],
+    let mut chunk = ChunkBuilder {
+        chunk_pos,
+        chunk: ServerChunk {
+            blocks: PalettedBlocks::default(),
+            // Freshly generated chunks have not received any update yet
+            ts: 0,
+            biomes: ColumnBiomes::default(),
+        },
+        spilled_blocks: PendingBlocks::new(),
     };
 
     for dx in 0..CHUNK_SIZE {
@@ -279,155 +339,249 @@ pub fn generate_chunk(chunk_pos: IVec3, seed: u32) -> ServerChunk {
                 (humidity_perlin.get([x as f64 * biome_scale, z as f64 * biome_scale]) + 1.0) / 2.0;
 
             // get biome regarding the two values
-            let biome_type = determine_biome(temperature, humidity);
-            let biome = get_biome_data(biome_type);
-
-            // get terrain height
-            let terrain_height = interpolated_height(
-                x,
-                z,
-                biome_scale,
-                &perlin,
-                &temp_perlin,
-                &humidity_perlin,
-                scale,
-            );
+            let biome = forced_biome.unwrap_or_else(|| biomes.find(temperature, humidity));
+            chunk.chunk.biomes.set(dx, dz, &biome.name);
+
+            // get terrain height, there is nothing to blend with in a single biome
+            let terrain_height = if forced_biome.is_some() {
+                let terrain_noise = perlin.get([x as f64 * scale, z as f64 * scale]);
+                (biome.base_height as f64 + biome.height_variation as f64 * terrain_noise).round()
+                    as i32
+            } else {
+                interpolated_height(
+                    x,
+                    z,
+                    biome_scale,
+                    &perlin,
+                    &temp_perlin,
+                    &humidity_perlin,
+                    scale,
+                    biomes,
+                )
+            };
+
+            // dig rivers and lakes, their banks are covered with sand and gravel
+            let column = water_shaper.shape_column(x, z, terrain_height);
+            let terrain_height = column.terrain_height;
+            let (surface_block, sub_surface_block) = column
+                .shore_blocks
+                .unwrap_or((biome.surface_block, biome.sub_surface_block));
+
+            let overhangs = biome.overhangs;
+            let ravine_floor = carver.ravine_floor(x, z, terrain_height);
+            let max_height = if overhangs {
+                terrain_height + OVERHANG_AMPLITUDE
+            } else {
+                terrain_height
+            };
+
+            let is_solid = |y: i32| {
+                if y == 0 {
+                    return true;
+                }
+                if y > max_height
+                    || y as f64 > carver.surface_height(x, y, z, terrain_height, overhangs)
+                {
+                    return false;
+                }
+                !carver.is_carved(x, y, z, terrain_height, ravine_floor)
+            };
+
+            // generate blocs, the block above the chunk is needed to find the surface
+            let solid: Vec<bool> = (0..=CHUNK_SIZE)
+                .map(|dy| is_solid(CHUNK_SIZE * cy + dy))
+                .collect();
 
-            // generate blocs
             for dy in 0..CHUNK_SIZE {
                 let y = CHUNK_SIZE * cy + dy;
+                let (solid, solid_above) = (solid[dy as usize], solid[dy as usize + 1]);
 
-                if y > terrain_height && y > 62 {
+                if y > max_height && y > SEA_LEVEL {
                     break;
                 }
 
-                let block = if y == 0 {
+                // Blocks near the heightmap are covered with the biome blocks,
+                // while the floors of caves are left bare
+                let near_surface = y >= terrain_height - 4;
+                let is_surface = solid && !solid_above && near_surface;
+
+                let block = if !solid {
+                    // Caves and ravines below sea level stay dry
+                    if y > terrain_height && y <= SEA_LEVEL {
+                        BlockId::Water
+                    } else {
+                        continue;
+                    }
+                } else if y == 0 {
                     BlockId::Bedrock
-                } else if y < terrain_height - 4 {
-                    BlockId::Stone
-                } else if y < terrain_height {
-                    biome.sub_surface_block
-                } else if y == terrain_height {
-                    biome.surface_block
-                } else if y <= 62 {
-                    BlockId::Water
+                } else if is_surface {
+                    surface_block
+                } else if near_surface {
+                    sub_surface_block
                 } else {
-                    panic!();
+                    BlockId::Stone
                 };
 
                 let block_pos = IVec3::new(dx, dy, dz);
 
-                chunk
-                    .map
-                    .insert(block_pos, BlockData::new(block, BlockDirection::Front));
+                chunk.insert(block_pos, BlockData::new(block, BlockDirection::Front));
 
                 // Add flora in biomes
-                if y == terrain_height && terrain_height > 62 {
-                    let above_surface_pos = IVec3::new(dx, terrain_height + 1, dz);
-
-                    // Add flowers
-                    let flower_chance = rand::random::<f32>();
-                    match biome_type {
-                        BiomeType::FlowerPlains => {
-                            // High probability for flowers in Flower Plains
-                            if flower_chance < 0.1 {
-                                let flower_type = if rand::random::<f32>() < 0.5 {
-                                    BlockId::Dandelion
-                                } else {
-                                    BlockId::Poppy
-                                };
-
-                                chunk.map.insert(
-                                    block_pos.with_y(block_pos.y + 1),
-                                    BlockData::new(flower_type, BlockDirection::Front),
-                                );
-                            }
-                        }
-                        BiomeType::Plains | BiomeType::Forest | BiomeType::MediumMountain => {
-                            // Low probability for flowers in Plains, Forest, Medium Mountain
-                            if flower_chance < 0.02 {
-                                let flower_type = if rand::random::<f32>() < 0.5 {
-                                    BlockId::Dandelion
-                                } else {
-                                    BlockId::Poppy
-                                };
-
-                                chunk.map.insert(
-                                    block_pos.with_y(block_pos.y + 1),
-                                    BlockData::new(flower_type, BlockDirection::Front),
-                                );
-                            }
-                        }
-                        _ => {}
-                    }
-
-                    // Add tall grass
-                    if biome_type != BiomeType::HighMountainGrass
-                        && biome_type != BiomeType::Desert
-                        && biome_type != BiomeType::IcePlain
-                    {
-                        let tall_grass_chance = rand::random::<f32>();
-                        if tall_grass_chance < 0.10 {
-                            chunk.map.insert(
-                                block_pos.with_y(block_pos.y + 1),
-                                BlockData::new(BlockId::TallGrass, BlockDirection::Front),
+                if is_surface && y > SEA_LEVEL && column.shore_blocks.is_none() {
+                    let above_surface_pos = block_pos.with_y(block_pos.y + 1);
+
+                    // Add decorations, at most one per surface block
+                    for decoration in biome.decorations.iter() {
+                        if rng.gen::<f32>() < decoration.density {
+                            chunk.insert(
+                                above_surface_pos,
+                                BlockData::new(decoration.block, BlockDirection::Front),
                             );
+                            break;
                         }
                     }
 
-                    // Add trees
-                    let tree_chance = rand::random::<f32>();
-                    match biome_type {
-                        BiomeType::Forest => {
-                            // High probability for trees in Forest
-                            if tree_chance < 0.06 && !chunk.map.contains_key(&above_surface_pos) {
-                                if tree_chance < 0.01 {
-                                    generate_big_tree(
-                                        &mut chunk,
-                                        dx,
-                                        dy + 1,
-                                        dz,
-                                        BlockId::OakLog,
-                                        BlockId::OakLeaves,
-                                    );
-                                } else {
-                                    generate_tree(
-                                        &mut chunk,
-                                        dx,
-                                        dy + 1,
-                                        dz,
-                                        BlockId::OakLog,
-                                        BlockId::OakLeaves,
-                                    );
-                                }
-                            }
-                        }
-                        BiomeType::FlowerPlains | BiomeType::MediumMountain => {
-                            // Medium probability for trees in Flower Plains and Medium Mountain
-                            if tree_chance < 0.02 && !chunk.map.contains_key(&above_surface_pos) {
-                                generate_tree(
+                    // Add trees, the chances of every kind of tree are stacked
+                    if !biome.trees.is_empty() && !chunk.contains(&above_surface_pos) {
+                        let mut tree_chance = rng.gen::<f32>();
+                        for tree in biome.trees.iter() {
+                            if tree_chance < tree.density {
+                                generate_tree_kind(
                                     &mut chunk,
+                                    &mut rng,
                                     dx,
                                     dy + 1,
                                     dz,
-                                    BlockId::OakLog,
-                                    BlockId::OakLeaves,
+                                    &tree.kind,
                                 );
+                                break;
                             }
+                            tree_chance -= tree.density;
                         }
-                        _ => {}
                     }
+                }
+            }
+        }
+    }
 
-                    // Add cactus in Desert
-                    if biome_type == BiomeType::Desert {
-                        let cactus_chance = rand::random::<f32>();
-                        if cactus_chance < 0.01 && !chunk.map.contains_key(&above_surface_pos) {
-                            generate_cactus(&mut chunk, dx, dy + 1, dz, BlockId::Cactus);
-                        }
-                    }
+    generate_ores(&mut chunk.chunk.blocks, &mut rng, chunk_pos);
+
+    GeneratedChunk {
+        chunk: chunk.chunk,
+        spilled_blocks: chunk.spilled_blocks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u32 = 1234;
+
+    /// Chunks around the surface, which the builtin biomes place around y = 64
+    fn surface_chunk_positions() -> Vec<IVec3> {
+        let mut positions = Vec::new();
+        for x in -2..2 {
+            for z in -2..2 {
+                for y in 3..=4 {
+                    positions.push(IVec3::new(x, y, z));
                 }
             }
         }
+        positions
+    }
+
+    /// Serialized chunk, along with its spilled blocks in a stable order
+    fn generate_payload(
+        chunk_pos: IVec3,
+        biomes: &BiomeRegistry,
+        forced_biome: Option<&BiomeDefinition>,
+    ) -> (Vec<u8>, Vec<(IVec3, IVec3, BlockId)>) {
+        let generated = generate_chunk(chunk_pos, SEED, biomes, forced_biome);
+
+        let mut spilled: Vec<(IVec3, IVec3, BlockId)> = generated
+            .spilled_blocks
+            .iter()
+            .flat_map(|(chunk_pos, blocks)| {
+                blocks
+                    .iter()
+                    .map(move |(pos, block)| (*chunk_pos, *pos, block.id))
+            })
+            .collect();
+        spilled.sort_by_key(|(chunk_pos, pos, _)| (chunk_pos.to_array(), pos.to_array()));
+
+        (bincode::serialize(&generated.chunk).unwrap(), spilled)
+    }
+
+    fn contains_block(payloads: &[(Vec<u8>, Vec<(IVec3, IVec3, BlockId)>)], id: BlockId) -> bool {
+        payloads.iter().any(|(chunk, spilled)| {
+            let chunk: ServerChunk = bincode::deserialize(chunk).unwrap();
+            chunk.blocks.iter().any(|(_, block)| block.id == id)
+                || spilled.iter().any(|(_, _, block)| *block == id)
+        })
+    }
+
+    /// Generates the same chunks twice, in opposite orders, and checks they are byte-identical
+    fn assert_deterministic(biome: Option<&str>, expected_blocks: &[BlockId]) {
+        let biomes = BiomeRegistry::builtin();
+        let forced_biome = biome.map(|name| biomes.get(name).unwrap());
+        let positions = surface_chunk_positions();
+
+        let first: Vec<_> = positions
+            .iter()
+            .map(|chunk_pos| generate_payload(*chunk_pos, &biomes, forced_biome))
+            .collect();
+        let mut second: Vec<_> = positions
+            .iter()
+            .rev()
+            .map(|chunk_pos| generate_payload(*chunk_pos, &biomes, forced_biome))
+            .collect();
+        second.reverse();
+
+        for ((chunk_pos, first), second) in positions.iter().zip(first.iter()).zip(second.iter()) {
+            assert_eq!(
+                first, second,
+                "chunk {chunk_pos:?} differs between generations"
+            );
+        }
+
+        for id in expected_blocks {
+            assert!(
+                contains_block(&first, *id),
+                "no {id:?} generated in {biome:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn forest_chunks_are_deterministic() {
+        assert_deterministic(
+            Some("Forest"),
+            &[BlockId::OakLog, BlockId::OakLeaves, BlockId::TallGrass],
+        );
+    }
+
+    #[test]
+    fn desert_chunks_are_deterministic() {
+        assert_deterministic(Some("Desert"), &[BlockId::Cactus]);
+    }
+
+    #[test]
+    fn flower_plains_chunks_are_deterministic() {
+        assert_deterministic(Some("FlowerPlains"), &[BlockId::Dandelion, BlockId::Poppy]);
+    }
+
+    #[test]
+    fn mixed_biome_chunks_are_deterministic() {
+        assert_deterministic(None, &[]);
+    }
+
+    #[test]
+    fn chunk_rng_depends_on_seed_and_position() {
+        let sample = |seed, chunk_pos| chunk_rng(seed, chunk_pos).gen::<u64>();
+
+        assert_eq!(sample(SEED, IVec3::ONE), sample(SEED, IVec3::ONE));
+        assert_ne!(sample(SEED, IVec3::ONE), sample(SEED + 1, IVec3::ONE));
+        assert_ne!(sample(SEED, IVec3::ONE), sample(SEED, IVec3::new(1, 1, 2)));
     }
-    chunk
 }
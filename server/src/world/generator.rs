@@ -0,0 +1,165 @@
+use bevy::prelude::*;
+use shared::world::{
+    global_block_to_chunk_pos, to_local_pos, BiomeDefinition, BiomeRegistry, BlockData,
+    BlockDirection, BlockId, ColumnBiomes, PalettedBlocks, PendingBlocks, ServerChunk,
+    WorldGeneratorConfig,
+};
+use shared::CHUNK_SIZE;
+use std::sync::Arc;
+
+use crate::world::generation::{generate_chunk, GeneratedChunk};
+
+/// Produces the content of chunks which were never generated before.\
+/// The result must only depend on the chunk position and the world the generator was built for
+pub trait WorldGenerator: Send + Sync {
+    fn generate_chunk(&self, chunk_pos: IVec3) -> GeneratedChunk;
+}
+
+/// Generator of the current world, built from the configuration saved with it
+#[derive(Resource)]
+pub struct ActiveWorldGenerator {
+    pub config: WorldGeneratorConfig,
+    /// Shared with the tasks generating chunks in the background
+    pub generator: Arc<dyn WorldGenerator>,
+}
+
+impl ActiveWorldGenerator {
+    /// Fails if the configuration refers to a biome which is not registered
+    pub fn new(
+        config: WorldGeneratorConfig,
+        seed: u32,
+        biomes: Arc<BiomeRegistry>,
+    ) -> Result<Self, String> {
+        let generator: Arc<dyn WorldGenerator> = match &config {
+            WorldGeneratorConfig::Noise => Arc::new(NoiseGenerator { seed, biomes }),
+            WorldGeneratorConfig::Superflat { layers } => Arc::new(SuperflatGenerator {
+                layers: layers.clone(),
+            }),
+            WorldGeneratorConfig::Void => Arc::new(VoidGenerator),
+            WorldGeneratorConfig::SingleBiome(name) => {
+                let biome = biomes
+                    .get(name)
+                    .ok_or_else(|| format!("Unknown biome : {name}"))?
+                    .clone();
+                Arc::new(SingleBiomeGenerator {
+                    seed,
+                    biomes,
+                    biome,
+                })
+            }
+        };
+
+        Ok(Self { config, generator })
+    }
+}
+
+/// Generated chunk made of the given blocks, without structures
+fn chunk_from_blocks(blocks: PalettedBlocks) -> GeneratedChunk {
+    GeneratedChunk {
+        chunk: ServerChunk {
+            blocks,
+            ts: 0,
+            biomes: ColumnBiomes::default(),
+        },
+        spilled_blocks: PendingBlocks::new(),
+    }
+}
+
+/// Default generator : biomes, caves, ores and structures
+pub struct NoiseGenerator {
+    seed: u32,
+    biomes: Arc<BiomeRegistry>,
+}
+
+impl WorldGenerator for NoiseGenerator {
+    fn generate_chunk(&self, chunk_pos: IVec3) -> GeneratedChunk {
+        generate_chunk(chunk_pos, self.seed, &self.biomes, None)
+    }
+}
+
+/// Noise generator where the whole world is a single biome, mostly to test biomes
+pub struct SingleBiomeGenerator {
+    seed: u32,
+    biomes: Arc<BiomeRegistry>,
+    biome: BiomeDefinition,
+}
+
+impl WorldGenerator for SingleBiomeGenerator {
+    fn generate_chunk(&self, chunk_pos: IVec3) -> GeneratedChunk {
+        generate_chunk(chunk_pos, self.seed, &self.biomes, Some(&self.biome))
+    }
+}
+
+/// Horizontal layers of blocks, stacked from y = 0
+pub struct SuperflatGenerator {
+    layers: Vec<(BlockId, u32)>,
+}
+
+impl SuperflatGenerator {
+    fn block_at(&self, y: i32) -> Option<BlockId> {
+        if y < 0 {
+            return None;
+        }
+
+        // Layers may come from a hand-edited save, so huge thicknesses must not overflow
+        let mut layer_top: i32 = 0;
+        for (block, thickness) in self.layers.iter() {
+            layer_top = layer_top.saturating_add(i32::try_from(*thickness).unwrap_or(i32::MAX));
+            if y < layer_top {
+                return Some(*block);
+            }
+        }
+
+        None
+    }
+}
+
+impl WorldGenerator for SuperflatGenerator {
+    fn generate_chunk(&self, chunk_pos: IVec3) -> GeneratedChunk {
+        let mut blocks = PalettedBlocks::default();
+
+        for dy in 0..CHUNK_SIZE {
+            let Some(block) = self.block_at(CHUNK_SIZE * chunk_pos.y + dy) else {
+                continue;
+            };
+
+            for dx in 0..CHUNK_SIZE {
+                for dz in 0..CHUNK_SIZE {
+                    blocks.insert(
+                        IVec3::new(dx, dy, dz),
+                        BlockData::new(block, BlockDirection::Front),
+                    );
+                }
+            }
+        }
+
+        chunk_from_blocks(blocks)
+    }
+}
+
+/// Height of the platform players spawn on in void worlds, below the default spawn position
+const VOID_PLATFORM_HEIGHT: i32 = 63;
+const VOID_PLATFORM_RADIUS: i32 = 2;
+
+/// Empty world, with a small platform around the origin so that new players don't fall forever
+pub struct VoidGenerator;
+
+impl WorldGenerator for VoidGenerator {
+    fn generate_chunk(&self, chunk_pos: IVec3) -> GeneratedChunk {
+        let mut blocks = PalettedBlocks::default();
+
+        for x in -VOID_PLATFORM_RADIUS..=VOID_PLATFORM_RADIUS {
+            for z in -VOID_PLATFORM_RADIUS..=VOID_PLATFORM_RADIUS {
+                let global_pos = IVec3::new(x, VOID_PLATFORM_HEIGHT, z);
+                if global_block_to_chunk_pos(&global_pos) == chunk_pos {
+                    blocks.insert(
+                        to_local_pos(&global_pos),
+                        BlockData::new(BlockId::Stone, BlockDirection::Front),
+                    );
+                }
+            }
+        }
+
+        chunk_from_blocks(blocks)
+    }
+}
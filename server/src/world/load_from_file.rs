@@ -1,17 +1,23 @@
 use bevy::prelude::*;
-use ron::de::from_str;
-use shared::messages::{PlayerId, PlayerSave};
+use shared::messages::PlayerSave;
 use shared::world::data::WorldSeed;
+use shared::world::WorldGeneratorConfig;
 use shared::GameFolderPaths;
 use std::fs;
 use std::path::Path;
 
-use crate::world::data::SAVE_PATH;
-use crate::world::save::WorldData;
+use crate::world::backup::create_backup;
+use crate::world::data::{get_player_save_path, get_region_folder_path, SAVE_PATH};
+use crate::world::migration::{
+    migrate_world, parse_player_save, parse_world_save, SAVE_FORMAT_VERSION,
+};
+use crate::world::save::{save_world_data, WorldData};
 use std::path::PathBuf;
 
+/// Loads a world from its save, or creates it with `generator` if it does not exist yet
 pub fn load_world_data(
     file_name: &str,
+    generator: &WorldGeneratorConfig,
     game_folder_paths: &GameFolderPaths,
 ) -> Result<WorldData, Box<dyn std::error::Error>> {
     let file_path: PathBuf = game_folder_paths
@@ -22,42 +28,81 @@ pub fn load_world_data(
 
     if !path.exists() {
         info!(
-            "World data file not found: {}. Generating default world and seed.",
-            file_path.display()
+            "World data file not found: {}. Generating new world and seed with generator {:?}.",
+            file_path.display(),
+            generator
         );
         let seed = WorldSeed(rand::random::<u32>());
-        return Ok(WorldData {
+        let world_data = WorldData {
             name: file_name.to_string(),
             seed,
+            generator: generator.clone(),
             ..default()
-        });
+        };
+
+        // Chunks can be written to disk as soon as they are unloaded, so the seed
+        // they were generated with must be saved right away
+        if let Some(world_folder) = path.parent() {
+            fs::create_dir_all(world_folder)?;
+        }
+        save_world_data(&world_data, &file_path.display().to_string())?;
+
+        return Ok(world_data);
     }
 
     let contents: String = fs::read_to_string(path)?;
-    let world_data: WorldData = from_str(&contents)?;
+    let (mut world_data, version) = parse_world_save(&contents)?;
+
+    info!(
+        "Found world data file from disk: {} (save format {})",
+        file_path.display(),
+        version
+    );
+
+    if version < SAVE_FORMAT_VERSION {
+        // Keep the save as it was before migrating, in case anything goes wrong
+        if let Some(backup) = create_backup(game_folder_paths, file_name)? {
+            info!(
+                "[{}] Created backup {} before migrating",
+                file_name, backup.name
+            );
+        }
+
+        migrate_world(
+            &mut world_data,
+            version,
+            &get_region_folder_path(game_folder_paths, file_name),
+        )?;
+        save_world_data(&world_data, &file_path.display().to_string())?;
+    }
 
-    info!("Found world data file from disk: {}", file_path.display());
+    // Chunks are loaded lazily from region files as players get close to them
+    world_data.map.clear();
 
     Ok(world_data)
 }
 
 pub fn load_player_data(
     world_name: &str,
-    player_id: &PlayerId,
+    username: &str,
     game_folder_paths: &GameFolderPaths,
 ) -> PlayerSave {
-    let file_path: PathBuf = game_folder_paths
-        .game_folder_path
-        .join(SAVE_PATH)
-        .join(format!("{world_name}/players/{player_id}.ron"));
+    let file_path = get_player_save_path(game_folder_paths, world_name, username);
     let path: &Path = file_path.as_path();
 
     if path.exists() {
         if let Ok(contents) = fs::read_to_string(path) {
-            if let Ok(player_data) = from_str::<PlayerSave>(&contents) {
-                info!("Found player data file from disk: {}", file_path.display());
+            match parse_player_save(&contents) {
+                Ok(player_data) => {
+                    info!("Found player data file from disk: {}", file_path.display());
 
-                return player_data;
+                    return player_data;
+                }
+                Err(err) => error!(
+                    "Could not read player data file {} : {}",
+                    file_path.display(),
+                    err
+                ),
             }
         }
     } else {
@@ -69,7 +114,6 @@ pub fn load_player_data(
 
     PlayerSave {
         position: Vec3::new(0., 80., 0.),
-        camera_transform: Transform::default(),
-        is_flying: false,
+        ..default()
     }
 }
@@ -0,0 +1,373 @@
+//! Versioning of the save files.
+//!
+//! RON saves (`world.ron`, `players/*.ron`) are wrapped in a `(version: _, data: _)` header,
+//! and every chunk payload in the region files is tagged with its own version.
+//! Older saves are upgraded on load, one version at a time.
+//!
+//! Save format history :
+//! - 0 : no version header, every chunk is stored inside `world.ron`
+//! - 1 : version header, chunks are stored in region files
+//!
+//! Chunk format history :
+//! - 1 : `ServerChunk` encoded with bincode + lz4, one map entry per block
+//! - 2 : blocks are stored in a palette with bit-packed indices
+//! - 3 : the biome of every column is stored along with the blocks
+//! - 4 : the clients a chunk was sent to are no longer stored, the server tracks them per player
+
+use bevy::prelude::*;
+use ron::de::from_str;
+use ron::ser::PrettyConfig;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use shared::messages::{PlayerId, PlayerSave};
+use shared::world::{BlockData, ColumnBiomes, PalettedBlocks, ServerChunk};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::world::region::save_chunks;
+use crate::world::save::WorldData;
+
+/// Version written in the header of the RON save files
+pub const SAVE_FORMAT_VERSION: u32 = 1;
+
+/// Version written in front of every chunk payload of the region files
+pub const CHUNK_FORMAT_VERSION: u32 = 4;
+
+/// `ServerChunk` as stored up to chunk format 1, also found in `world.ron` for save format 0
+#[derive(Deserialize)]
+pub struct ServerChunkV1 {
+    map: HashMap<IVec3, BlockData>,
+    ts: u64,
+    #[allow(dead_code)]
+    sent_to_clients: Vec<PlayerId>,
+}
+
+impl From<ServerChunkV1> for ServerChunk {
+    fn from(chunk: ServerChunkV1) -> Self {
+        ServerChunk {
+            blocks: chunk.map.into_iter().collect(),
+            ts: chunk.ts,
+            biomes: ColumnBiomes::default(),
+        }
+    }
+}
+
+/// `ServerChunk` as stored in chunk format 2, before column biomes were saved
+#[derive(Deserialize)]
+struct ServerChunkV2 {
+    blocks: PalettedBlocks,
+    ts: u64,
+    #[allow(dead_code)]
+    sent_to_clients: Vec<PlayerId>,
+}
+
+impl From<ServerChunkV2> for ServerChunk {
+    fn from(chunk: ServerChunkV2) -> Self {
+        // The biomes of these chunks are unknown, clients fall back to the default colors
+        ServerChunk {
+            blocks: chunk.blocks,
+            ts: chunk.ts,
+            biomes: ColumnBiomes::default(),
+        }
+    }
+}
+
+/// `ServerChunk` as stored in chunk format 3, before clients were tracked per player
+#[derive(Deserialize)]
+struct ServerChunkV3 {
+    blocks: PalettedBlocks,
+    ts: u64,
+    #[allow(dead_code)]
+    sent_to_clients: Vec<PlayerId>,
+    biomes: ColumnBiomes,
+}
+
+impl From<ServerChunkV3> for ServerChunk {
+    fn from(chunk: ServerChunkV3) -> Self {
+        ServerChunk {
+            blocks: chunk.blocks,
+            ts: chunk.ts,
+            biomes: chunk.biomes,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VersionedSaveRef<'a, T> {
+    version: u32,
+    data: &'a T,
+}
+
+#[derive(Deserialize)]
+struct VersionedSave<T> {
+    data: T,
+}
+
+/// Only reads the version of a save, whatever the layout of its data
+#[derive(Deserialize)]
+struct VersionHeader {
+    #[serde(default)]
+    version: u32,
+}
+
+/// Serializes data to RON, behind a header holding the current save format version
+pub fn to_versioned_ron<T: Serialize>(
+    data: &T,
+    pretty_config: PrettyConfig,
+) -> Result<String, ron::Error> {
+    ron::ser::to_string_pretty(
+        &VersionedSaveRef {
+            version: SAVE_FORMAT_VERSION,
+            data,
+        },
+        pretty_config,
+    )
+}
+
+/// Returns the save format version of a RON save, 0 for legacy saves without header
+pub fn read_save_version(contents: &str) -> Result<u32, Box<dyn std::error::Error>> {
+    let version = from_str::<VersionHeader>(contents)?.version;
+
+    if version > SAVE_FORMAT_VERSION {
+        return Err(format!(
+            "save format version {version} is newer than the latest supported version {SAVE_FORMAT_VERSION}"
+        )
+        .into());
+    }
+
+    Ok(version)
+}
+
+fn parse_save<T: DeserializeOwned>(contents: &str, version: u32) -> Result<T, ron::Error> {
+    if version == 0 {
+        from_str::<T>(contents)
+    } else {
+        Ok(from_str::<VersionedSave<T>>(contents)?.data)
+    }
+}
+
+/// Reads a world save of any supported version.\
+/// Returns the world data along with the version it was saved with,
+/// `migrate_world` must be called if it is older than `SAVE_FORMAT_VERSION`
+pub fn parse_world_save(contents: &str) -> Result<(WorldData, u32), Box<dyn std::error::Error>> {
+    let version = read_save_version(contents)?;
+
+    // Versions 0 and 1 only differ by where chunks are stored, `WorldData::map` still reads them
+    let world_data = parse_save::<WorldData>(contents, version)?;
+
+    Ok((world_data, version))
+}
+
+/// Upgrades a world read from an older save to the current format, one version at a time
+pub fn migrate_world(
+    world_data: &mut WorldData,
+    from_version: u32,
+    region_folder: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for version in from_version..SAVE_FORMAT_VERSION {
+        info!(
+            "[{}] Migrating world from save format {} to {}",
+            world_data.name,
+            version,
+            version + 1
+        );
+
+        match version {
+            0 => {
+                // Chunks move out of world.ron into region files
+                let chunks: HashMap<IVec3, ServerChunk> = world_data
+                    .map
+                    .drain()
+                    .map(|(chunk_pos, chunk)| (chunk_pos, chunk.into()))
+                    .collect();
+                save_chunks(region_folder, &chunks)?;
+            }
+            _ => unreachable!("no migration from save format {version}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a player save of any supported version
+pub fn parse_player_save(contents: &str) -> Result<PlayerSave, Box<dyn std::error::Error>> {
+    let version = read_save_version(contents)?;
+
+    // Fields added to `PlayerSave` have defaults, and the extra fields of files written
+    // from a whole `Player` are ignored, so every version shares the same layout
+    Ok(parse_save::<PlayerSave>(contents, version)?)
+}
+
+/// Decodes a chunk payload read from a region file, upgrading it if it was written by an older version
+pub fn decode_chunk(
+    version: u32,
+    payload: &[u8],
+) -> Result<ServerChunk, Box<dyn std::error::Error>> {
+    match version {
+        1 => Ok(shared::payload_to_game_message::<ServerChunkV1>(payload)?.into()),
+        2 => Ok(shared::payload_to_game_message::<ServerChunkV2>(payload)?.into()),
+        3 => Ok(shared::payload_to_game_message::<ServerChunkV3>(payload)?.into()),
+        CHUNK_FORMAT_VERSION => Ok(shared::payload_to_game_message::<ServerChunk>(payload)?),
+        _ => Err(format!(
+            "chunk format version {version} is not supported (latest is {CHUNK_FORMAT_VERSION})"
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::region::load_chunk;
+    use shared::world::{BlockDirection, BlockId, ItemId};
+    use std::fs;
+    use std::path::PathBuf;
+
+    // Files written by older versions of the server
+    const WORLD_SAVE_V0: &str = include_str!("../../tests/fixtures/world_v0.ron");
+    const WORLD_SAVE_V1: &str = include_str!("../../tests/fixtures/world_v1.ron");
+    const PLAYER_SAVE_V0: &str = include_str!("../../tests/fixtures/player_v0.ron");
+    const PLAYER_SAVE_V1: &str = include_str!("../../tests/fixtures/player_v1.ron");
+    const CHUNK_V1: &[u8] = include_bytes!("../../tests/fixtures/chunk_v1.bin");
+    const CHUNK_V2: &[u8] = include_bytes!("../../tests/fixtures/chunk_v2.bin");
+    const CHUNK_V3: &[u8] = include_bytes!("../../tests/fixtures/chunk_v3.bin");
+
+    fn test_region_folder(name: &str) -> PathBuf {
+        let folder =
+            std::env::temp_dir().join(format!("migration-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        folder
+    }
+
+    /// Blocks stored in every chunk of the fixtures
+    fn assert_fixture_blocks(chunk: &ServerChunk) {
+        let expected = [
+            (IVec3::new(0, 0, 0), BlockId::Bedrock, BlockDirection::Front),
+            (IVec3::new(1, 0, 0), BlockId::Stone, BlockDirection::Front),
+            (IVec3::new(0, 1, 0), BlockId::Dirt, BlockDirection::Front),
+            (IVec3::new(0, 2, 0), BlockId::Grass, BlockDirection::Front),
+            (IVec3::new(5, 3, 7), BlockId::OakLog, BlockDirection::Right),
+        ];
+
+        assert_eq!(chunk.blocks.len(), expected.len());
+        for (pos, id, direction) in expected {
+            assert_eq!(
+                chunk.blocks.get(&pos),
+                Some(&BlockData::new(id, direction)),
+                "block at {pos}"
+            );
+        }
+    }
+
+    #[test]
+    fn chunk_format_1_is_upgraded() {
+        let chunk = decode_chunk(1, CHUNK_V1).unwrap();
+
+        assert_fixture_blocks(&chunk);
+        assert_eq!(chunk.ts, 11);
+        assert_eq!(chunk.biomes.get(0, 0), None);
+    }
+
+    #[test]
+    fn chunk_format_2_is_upgraded() {
+        let chunk = decode_chunk(2, CHUNK_V2).unwrap();
+
+        assert_fixture_blocks(&chunk);
+        assert_eq!(chunk.ts, 12);
+        assert_eq!(chunk.biomes.get(0, 0), None);
+    }
+
+    #[test]
+    fn chunk_format_3_is_upgraded() {
+        let chunk = decode_chunk(3, CHUNK_V3).unwrap();
+
+        assert_fixture_blocks(&chunk);
+        assert_eq!(chunk.ts, 13);
+        assert_eq!(chunk.biomes.get(0, 0), Some("Plains"));
+        assert_eq!(chunk.biomes.get(15, 15), Some("Forest"));
+    }
+
+    #[test]
+    fn unknown_chunk_formats_are_rejected() {
+        assert!(decode_chunk(0, CHUNK_V1).is_err());
+        assert!(decode_chunk(CHUNK_FORMAT_VERSION + 1, CHUNK_V1).is_err());
+    }
+
+    #[test]
+    fn world_save_format_0_is_migrated() {
+        let (mut world_data, version) = parse_world_save(WORLD_SAVE_V0).unwrap();
+
+        assert_eq!(version, 0);
+        assert_eq!(world_data.name, "legacy");
+        assert_eq!(world_data.seed.0, 1234);
+        assert_eq!(world_data.time, 5000);
+        assert_eq!(world_data.map.len(), 2);
+
+        // Chunks move out of world.ron into region files
+        let region_folder = test_region_folder("world-v0");
+        migrate_world(&mut world_data, version, &region_folder).unwrap();
+        assert!(world_data.map.is_empty());
+
+        let chunk = load_chunk(&region_folder, &IVec3::new(0, 0, 0))
+            .unwrap()
+            .unwrap();
+        assert_fixture_blocks(&chunk);
+        assert_eq!(chunk.ts, 11);
+
+        let chunk = load_chunk(&region_folder, &IVec3::new(-1, 2, 3))
+            .unwrap()
+            .unwrap();
+        assert_fixture_blocks(&chunk);
+        assert_eq!(chunk.ts, 7);
+
+        fs::remove_dir_all(&region_folder).unwrap();
+    }
+
+    #[test]
+    fn world_save_format_1_is_read() {
+        let (mut world_data, version) = parse_world_save(WORLD_SAVE_V1).unwrap();
+
+        assert_eq!(version, 1);
+        assert_eq!(world_data.name, "versioned");
+        assert_eq!(world_data.seed.0, 1234);
+        assert_eq!(world_data.time, 6000);
+        assert!(world_data.map.is_empty());
+        assert!(world_data.pending_blocks.is_empty());
+        assert_eq!(world_data.generator, Default::default());
+
+        // Nothing to migrate, so no region file gets written
+        let region_folder = test_region_folder("world-v1");
+        migrate_world(&mut world_data, version, &region_folder).unwrap();
+        assert!(!region_folder.exists());
+    }
+
+    #[test]
+    fn player_save_format_0_is_read() {
+        let player_save = parse_player_save(PLAYER_SAVE_V0).unwrap();
+
+        assert_eq!(player_save.position, Vec3::new(1.5, 80.0, -3.25));
+        assert_eq!(player_save.camera_transform.translation, Vec3::new(0.0, 1.8, 0.0));
+        assert!(!player_save.is_flying);
+
+        // Saved along with the whole player back then
+        let stack = player_save.inventory.inner.get(&0).unwrap();
+        assert_eq!(stack.item_id, ItemId::Dirt);
+        assert_eq!(stack.nb, 12);
+        assert_eq!(player_save.hotbar_slot, 0);
+    }
+
+    #[test]
+    fn player_save_format_1_is_read() {
+        let player_save = parse_player_save(PLAYER_SAVE_V1).unwrap();
+
+        assert_eq!(player_save.position, Vec3::new(-7.0, 64.0, 2.5));
+        assert!(player_save.is_flying);
+        assert!(player_save.inventory.inner.is_empty());
+    }
+
+    #[test]
+    fn newer_save_formats_are_rejected() {
+        let contents = format!("(version: {}, data: ())", SAVE_FORMAT_VERSION + 1);
+        assert!(read_save_version(&contents).is_err());
+    }
+}
@@ -1,11 +1,22 @@
 pub mod background_generation;
+pub mod backup;
 pub mod broadcast_world;
+pub mod carving;
 pub(crate) mod data;
 pub mod generation;
+pub mod generator;
 pub mod load_from_file;
+pub mod migration;
+pub mod ores;
+pub mod pregeneration;
+pub mod region;
+pub mod rivers;
 pub mod save;
 pub mod simulation;
 pub mod stacks;
+pub mod streaming;
+pub mod view;
+pub mod writer;
 
 use bevy::prelude::Event;
 use bevy::prelude::EventReader;
@@ -0,0 +1,101 @@
+use bevy::prelude::*;
+use rand::Rng;
+use shared::world::{BlockData, BlockDirection, BlockId, PalettedBlocks};
+use shared::CHUNK_SIZE;
+
+/// How an ore is spread underground
+struct OreConfig {
+    block: BlockId,
+    /// Global heights the veins can start at, inclusive
+    min_height: i32,
+    max_height: i32,
+    /// Average number of veins started in each chunk crossing the height range
+    veins_per_chunk: f32,
+    /// Maximum number of blocks of a vein
+    vein_size: u32,
+}
+
+const ORES: [OreConfig; 4] = [
+    OreConfig {
+        block: BlockId::CoalOre,
+        min_height: 5,
+        max_height: 128,
+        veins_per_chunk: 3.,
+        vein_size: 14,
+    },
+    OreConfig {
+        block: BlockId::IronOre,
+        min_height: 5,
+        max_height: 64,
+        veins_per_chunk: 2.,
+        vein_size: 8,
+    },
+    OreConfig {
+        block: BlockId::GoldOre,
+        min_height: 5,
+        max_height: 32,
+        veins_per_chunk: 0.5,
+        vein_size: 8,
+    },
+    OreConfig {
+        block: BlockId::DiamondOre,
+        min_height: 5,
+        max_height: 16,
+        veins_per_chunk: 0.5,
+        vein_size: 6,
+    },
+];
+
+/// Replaces stone with ore veins. Veins stay inside the chunk, and only replace stone,
+/// so that caves and the surface are left untouched
+pub fn generate_ores(blocks: &mut PalettedBlocks, rng: &mut impl Rng, chunk_pos: IVec3) {
+    let chunk_min_y = chunk_pos.y * CHUNK_SIZE;
+    let chunk_max_y = chunk_min_y + CHUNK_SIZE - 1;
+
+    for ore in ORES.iter() {
+        if ore.max_height < chunk_min_y || ore.min_height > chunk_max_y {
+            continue;
+        }
+
+        let mut nb_veins = ore.veins_per_chunk as u32;
+        if rng.gen::<f32>() < ore.veins_per_chunk.fract() {
+            nb_veins += 1;
+        }
+
+        for _ in 0..nb_veins {
+            let start = IVec3::new(
+                rng.gen_range(0..CHUNK_SIZE),
+                rng.gen_range(0..CHUNK_SIZE),
+                rng.gen_range(0..CHUNK_SIZE),
+            );
+
+            let y = chunk_min_y + start.y;
+            if y < ore.min_height || y > ore.max_height {
+                continue;
+            }
+
+            generate_vein(blocks, rng, start, ore);
+        }
+    }
+}
+
+/// Random walk from `start`, turning the stone it crosses into ore
+fn generate_vein(blocks: &mut PalettedBlocks, rng: &mut impl Rng, start: IVec3, ore: &OreConfig) {
+    let mut pos = start;
+
+    for _ in 0..ore.vein_size {
+        if blocks
+            .get(&pos)
+            .is_some_and(|block| block.id == BlockId::Stone)
+        {
+            blocks.insert(pos, BlockData::new(ore.block, BlockDirection::Front));
+        }
+
+        let step = match rng.gen_range(0..3) {
+            0 => IVec3::X,
+            1 => IVec3::Y,
+            _ => IVec3::Z,
+        };
+        pos += if rng.gen::<bool>() { step } else { -step };
+    }
+}
@@ -0,0 +1,326 @@
+use bevy::prelude::*;
+use bevy::tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task};
+use serde::{Deserialize, Serialize};
+use shared::world::{PregenerationArea, ServerWorldMap};
+use shared::{GameFolderPaths, GameServerConfig, CHUNK_SIZE};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::world::data::{get_region_folder_path, get_world_folder_path};
+use crate::world::generation::GeneratedChunk;
+use crate::world::generator::{ActiveWorldGenerator, WorldGenerator};
+use crate::world::region::chunk_exists;
+use crate::world::save::SaveRequestEvent;
+use crate::world::streaming::{insert_generated_chunk, ChunkActivity};
+use crate::world::writer::{WorldWriter, WriteId};
+
+/// Chunk layers generated in every column, covering the heights the terrain can reach
+const MIN_CHUNK_Y: i32 = 0;
+const MAX_CHUNK_Y: i32 = 256 / CHUNK_SIZE - 1;
+
+/// Number of columns generated in parallel before their chunks are written to disk
+/// and the progress is saved
+const BATCH_SIZE: usize = 64;
+
+/// Progress of the running pre-generation, kept in the world folder until it is done
+const PROGRESS_FILE_NAME: &str = "pregeneration.ron";
+
+#[derive(Event, Debug, Clone)]
+pub enum PregenerationCommand {
+    /// Starts generating an area, replacing the running pre-generation if any
+    Start(PregenerationArea),
+    Cancel,
+    /// Logs the progress of the running pre-generation
+    Status,
+}
+
+impl PregenerationCommand {
+    /// Parses the arguments of the `/pregen` chat command :
+    /// `radius <x> <z> <radius>`, `rect <x1> <z1> <x2> <z2>`, `cancel` or `status`
+    pub fn parse(args: &str) -> Result<Self, String> {
+        let args: Vec<&str> = args.split_whitespace().collect();
+        match args.as_slice() {
+            ["cancel"] => Ok(Self::Cancel),
+            ["status"] => Ok(Self::Status),
+            [kind, values @ ..] => Ok(Self::Start(PregenerationArea::parse(kind, values)?)),
+            [] => Err("Missing pre-generation area".into()),
+        }
+    }
+}
+
+/// Saved progress of a pre-generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PregenerationJob {
+    area: PregenerationArea,
+    /// Number of columns written to disk, in the order of `PregenerationArea::columns`
+    done_columns: usize,
+}
+
+/// Pre-generation currently running, at most one at a time
+#[derive(Resource, Default)]
+pub struct Pregeneration {
+    job: Option<PregenerationJob>,
+    columns: Vec<IVec2>,
+    /// End of the batch of columns being generated
+    batch_end: usize,
+    tasks: Vec<Task<Vec<(IVec3, GeneratedChunk)>>>,
+    /// Chunks of the current batch already merged into the world
+    batch_chunks: Vec<IVec3>,
+    /// Write of the chunks of the current batch, once all of them are generated
+    saving: Option<WriteId>,
+    generated_chunks: usize,
+    started_at: Option<Instant>,
+}
+
+impl Pregeneration {
+    fn start(&mut self, job: PregenerationJob) {
+        self.columns = job.area.columns();
+        self.batch_end = job.done_columns;
+        self.tasks.clear();
+        self.batch_chunks.clear();
+        self.saving = None;
+        self.generated_chunks = 0;
+        self.started_at = Some(Instant::now());
+        self.job = Some(job);
+    }
+
+    fn log_progress(&self) {
+        let Some(job) = &self.job else {
+            info!("No pre-generation running");
+            return;
+        };
+
+        let elapsed = self
+            .started_at
+            .map(|started_at| started_at.elapsed().as_secs_f64())
+            .unwrap_or_default();
+        info!(
+            "Pre-generation : {}/{} columns ({:.1}%), {} chunks generated ({:.0} chunks/s)",
+            job.done_columns,
+            self.columns.len(),
+            100. * job.done_columns as f64 / self.columns.len().max(1) as f64,
+            self.generated_chunks,
+            self.generated_chunks as f64 / elapsed.max(0.001)
+        );
+    }
+}
+
+fn progress_file_path(game_folder_paths: &GameFolderPaths, world_name: &str) -> PathBuf {
+    get_world_folder_path(game_folder_paths, world_name).join(PROGRESS_FILE_NAME)
+}
+
+fn save_progress(job: &PregenerationJob, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = ron::ser::to_string_pretty(job, ron::ser::PrettyConfig::default())?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+fn load_progress(path: &Path) -> Result<Option<PregenerationJob>, Box<dyn std::error::Error>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(ron::de::from_str(&fs::read_to_string(path)?)?))
+}
+
+/// Resumes the pre-generation interrupted by the last shutdown, unless another one
+/// was requested from the command line
+pub fn start_pregeneration_system(
+    config: Res<GameServerConfig>,
+    world_map: Res<ServerWorldMap>,
+    game_folder_paths: Res<GameFolderPaths>,
+    mut ev_command: EventWriter<PregenerationCommand>,
+    mut pregeneration: ResMut<Pregeneration>,
+) {
+    if let Some(area) = &config.pregenerate {
+        ev_command.write(PregenerationCommand::Start(area.clone()));
+        return;
+    }
+
+    match load_progress(&progress_file_path(&game_folder_paths, &world_map.name)) {
+        Ok(Some(job)) => {
+            info!(
+                "Resuming pre-generation of {:?} after {} columns",
+                job.area, job.done_columns
+            );
+            pregeneration.start(job);
+        }
+        Ok(None) => {}
+        Err(err) => error!("Could not read the pre-generation progress : {}", err),
+    }
+}
+
+pub fn handle_pregeneration_commands(
+    mut events: EventReader<PregenerationCommand>,
+    mut pregeneration: ResMut<Pregeneration>,
+    world_map: Res<ServerWorldMap>,
+    game_folder_paths: Res<GameFolderPaths>,
+) {
+    let progress_path = progress_file_path(&game_folder_paths, &world_map.name);
+
+    for event in events.read() {
+        match event {
+            PregenerationCommand::Start(area) => {
+                let job = PregenerationJob {
+                    area: area.clone(),
+                    done_columns: 0,
+                };
+                if let Err(err) = save_progress(&job, &progress_path) {
+                    error!("Could not save the pre-generation progress : {}", err);
+                }
+                info!(
+                    "Starting pre-generation of {:?}, {} columns",
+                    area,
+                    area.columns().len()
+                );
+                pregeneration.start(job);
+            }
+            PregenerationCommand::Cancel => {
+                if pregeneration.job.is_none() {
+                    info!("No pre-generation to cancel");
+                    continue;
+                }
+                // Chunks of the current batch are already in the world, they get saved with it
+                *pregeneration = Pregeneration::default();
+                if let Err(err) = fs::remove_file(&progress_path) {
+                    error!("Could not remove the pre-generation progress : {}", err);
+                }
+                info!("Pre-generation cancelled");
+            }
+            PregenerationCommand::Status => pregeneration.log_progress(),
+        }
+    }
+}
+
+/// Generates the chunks of a column which were never generated, on a background thread
+fn spawn_column_task(
+    generator: Arc<dyn WorldGenerator>,
+    region_folder: PathBuf,
+    chunk_positions: Vec<IVec3>,
+) -> Task<Vec<(IVec3, GeneratedChunk)>> {
+    AsyncComputeTaskPool::get().spawn(async move {
+        chunk_positions
+            .into_iter()
+            .filter(|chunk_pos| match chunk_exists(&region_folder, chunk_pos) {
+                Ok(exists) => !exists,
+                Err(err) => {
+                    // Never generate over a chunk that exists on disk but could not be read
+                    error!("Could not read chunk {:?} from disk : {}", chunk_pos, err);
+                    false
+                }
+            })
+            .map(|chunk_pos| (chunk_pos, generator.generate_chunk(chunk_pos)))
+            .collect()
+    })
+}
+
+pub fn pregeneration_system(
+    mut pregeneration: ResMut<Pregeneration>,
+    mut world_map: ResMut<ServerWorldMap>,
+    mut activity: ResMut<ChunkActivity>,
+    generator: Res<ActiveWorldGenerator>,
+    game_folder_paths: Res<GameFolderPaths>,
+    mut writer: ResMut<WorldWriter>,
+    mut ev_save_request: EventWriter<SaveRequestEvent>,
+) {
+    if pregeneration.job.is_none() {
+        return;
+    }
+
+    let pregeneration = pregeneration.as_mut();
+    let world_map = world_map.as_mut();
+    let region_folder = get_region_folder_path(&game_folder_paths, &world_map.name);
+
+    // Merge the columns which are done
+    let mut finished = Vec::new();
+    pregeneration
+        .tasks
+        .retain_mut(|task| match block_on(future::poll_once(task)) {
+            Some(chunks) => {
+                finished.extend(chunks);
+                false
+            }
+            None => true,
+        });
+
+    for (chunk_pos, generated) in finished {
+        // Players may have loaded the chunk in the meantime.
+        // Chunks already on disk were left out by the task, without reading them on the tick
+        if world_map.chunks.map.contains_key(&chunk_pos) {
+            continue;
+        }
+
+        insert_generated_chunk(&mut world_map.chunks, chunk_pos, generated);
+        pregeneration.batch_chunks.push(chunk_pos);
+        pregeneration.generated_chunks += 1;
+    }
+
+    if !pregeneration.tasks.is_empty() {
+        return;
+    }
+
+    let job = pregeneration.job.as_mut().unwrap();
+
+    if pregeneration.batch_end > job.done_columns {
+        // Write the batch through the world writer, the progress only moves on once it is on disk
+        let Some(saving) = pregeneration.saving else {
+            let progress_path = progress_file_path(&game_folder_paths, &world_map.name);
+            let mut written_job = job.clone();
+            written_job.done_columns = pregeneration.batch_end;
+
+            pregeneration.saving = Some(writer.queue_chunks(
+                &mut world_map.chunks,
+                pregeneration.batch_chunks.iter().copied(),
+                region_folder,
+                move || {
+                    if let Err(err) = save_progress(&written_job, &progress_path) {
+                        error!("Could not save the pre-generation progress : {}", err);
+                    }
+                },
+            ));
+
+            // Unloaded once written, unless players are around. Chunks changed
+            // in the meantime, e.g. by structures of their neighbours, are written again first
+            activity
+                .released
+                .extend(pregeneration.batch_chunks.drain(..));
+            return;
+        };
+
+        if !writer.is_done(saving) {
+            return;
+        }
+        pregeneration.saving = None;
+
+        job.done_columns = pregeneration.batch_end;
+        pregeneration.log_progress();
+    }
+
+    let job = pregeneration.job.as_ref().unwrap();
+
+    if job.done_columns >= pregeneration.columns.len() {
+        info!("Pre-generation of {:?} done", job.area);
+        if let Err(err) = fs::remove_file(progress_file_path(&game_folder_paths, &world_map.name)) {
+            error!("Could not remove the pre-generation progress : {}", err);
+        }
+        // Structures waiting for chunks outside of the area are only saved with the world
+        ev_save_request.write(SaveRequestEvent::World);
+        *pregeneration = Pregeneration::default();
+        return;
+    }
+
+    pregeneration.batch_end = (job.done_columns + BATCH_SIZE).min(pregeneration.columns.len());
+    for column in pregeneration.columns[job.done_columns..pregeneration.batch_end].iter() {
+        let chunk_positions = (MIN_CHUNK_Y..=MAX_CHUNK_Y)
+            .map(|y| IVec3::new(column.x, y, column.y))
+            .filter(|chunk_pos| !world_map.chunks.map.contains_key(chunk_pos))
+            .collect();
+
+        pregeneration.tasks.push(spawn_column_task(
+            Arc::clone(&generator.generator),
+            region_folder.clone(),
+            chunk_positions,
+        ));
+    }
+}
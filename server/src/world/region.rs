@@ -0,0 +1,455 @@
+use bevy::prelude::*;
+use shared::world::ServerChunk;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::world::migration::{decode_chunk, CHUNK_FORMAT_VERSION};
+
+/// Number of chunks along each axis of a region
+pub const REGION_SIZE: i32 = 32;
+const REGION_CHUNK_COUNT: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+/// Chunk data is allocated by sectors, so that the space left by a chunk
+/// can be reused by another one without moving the rest of the file
+const SECTOR_SIZE: u64 = 4096;
+
+/// Each index entry is `(first_sector: u32, sector_count: u32)`, a zeroed entry means "no chunk"
+const INDEX_ENTRY_SIZE: usize = 8;
+const HEADER_SECTORS: u32 =
+    ((REGION_CHUNK_COUNT * INDEX_ENTRY_SIZE) as u64).div_ceil(SECTOR_SIZE) as u32;
+
+/// Header written in front of every chunk payload : `(length: u32, format_version: u32)`
+const CHUNK_HEADER_SIZE: u64 = 8;
+
+const REGION_FILE_EXTENSION: &str = "region";
+
+/// Region files are accessed both from the tick and from background saves
+static REGION_FILES_LOCK: Mutex<()> = Mutex::new(());
+
+/// Number of region files being copied. Freed sectors are not reused in the meantime,
+/// so that every chunk indexed by a copied header is still intact when its data is copied
+static REGION_FILE_COPIES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct IndexEntry {
+    first_sector: u32,
+    sector_count: u32,
+}
+
+impl IndexEntry {
+    fn is_empty(&self) -> bool {
+        self.sector_count == 0
+    }
+
+    fn from_bytes(raw: &[u8]) -> Self {
+        Self {
+            first_sector: u32::from_le_bytes(raw[0..4].try_into().unwrap()),
+            sector_count: u32::from_le_bytes(raw[4..8].try_into().unwrap()),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; INDEX_ENTRY_SIZE] {
+        let mut raw = [0u8; INDEX_ENTRY_SIZE];
+        raw[0..4].copy_from_slice(&self.first_sector.to_le_bytes());
+        raw[4..8].copy_from_slice(&self.sector_count.to_le_bytes());
+        raw
+    }
+}
+
+/// A single region file on disk, grouping `REGION_SIZE`³ chunks.
+///
+/// Layout : a fixed-size header indexing every chunk slot, followed by
+/// sector-aligned chunk payloads (bincode + lz4, prefixed by their length and format version).
+///
+/// Chunks are never overwritten in place : they are written to free sectors,
+/// and the index only points to them once they are on disk. A crash in the middle
+/// of a save thus leaves every chunk either in its old or in its new state.
+struct RegionFile {
+    file: File,
+    index: Vec<IndexEntry>,
+    /// Which sectors of the file are referenced by the index (or by pending writes)
+    used_sectors: Vec<bool>,
+    /// Index entries of the chunks written since the last flush, with the entries they replace
+    pending_entries: Vec<(usize, IndexEntry, IndexEntry)>,
+}
+
+impl RegionFile {
+    fn open(path: &Path, create: bool) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            if !create {
+                return Ok(None);
+            }
+
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(path)?;
+            file.write_all(&vec![0u8; HEADER_SECTORS as usize * SECTOR_SIZE as usize])?;
+
+            return Ok(Some(Self::new(
+                file,
+                vec![IndexEntry::default(); REGION_CHUNK_COUNT],
+            )));
+        }
+
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        let mut header = vec![0u8; REGION_CHUNK_COUNT * INDEX_ENTRY_SIZE];
+        file.read_exact(&mut header)?;
+
+        let index = header
+            .chunks_exact(INDEX_ENTRY_SIZE)
+            .map(IndexEntry::from_bytes)
+            .collect();
+
+        Ok(Some(Self::new(file, index)))
+    }
+
+    fn new(file: File, index: Vec<IndexEntry>) -> Self {
+        let mut region = Self {
+            file,
+            index,
+            used_sectors: vec![true; HEADER_SECTORS as usize],
+            pending_entries: Vec::new(),
+        };
+
+        for entry in region.index.clone() {
+            if !entry.is_empty() {
+                region.set_sectors_used(entry, true);
+            }
+        }
+
+        region
+    }
+
+    fn set_sectors_used(&mut self, entry: IndexEntry, used: bool) {
+        let range = entry.first_sector as usize..(entry.first_sector + entry.sector_count) as usize;
+        if self.used_sectors.len() < range.end {
+            self.used_sectors.resize(range.end, false);
+        }
+        self.used_sectors[range].fill(used);
+    }
+
+    /// Finds the first run of `count` free sectors, or the end of the file
+    fn allocate_sectors(&mut self, count: u32) -> Result<IndexEntry, Box<dyn std::error::Error>> {
+        if REGION_FILE_COPIES.load(Ordering::SeqCst) > 0 {
+            // Sectors past the index may still be referenced by the header of a copy
+            let file_sectors = self.file.metadata()?.len().div_ceil(SECTOR_SIZE) as usize;
+            let entry = IndexEntry {
+                first_sector: self.used_sectors.len().max(file_sectors) as u32,
+                sector_count: count,
+            };
+            self.set_sectors_used(entry, true);
+            return Ok(entry);
+        }
+
+        let mut first_sector = HEADER_SECTORS as usize;
+        let mut run = 0;
+        for (sector, used) in self.used_sectors.iter().enumerate().skip(first_sector) {
+            if *used {
+                first_sector = sector + 1;
+                run = 0;
+            } else {
+                run += 1;
+                if run == count as usize {
+                    break;
+                }
+            }
+        }
+
+        let entry = IndexEntry {
+            first_sector: first_sector as u32,
+            sector_count: count,
+        };
+        self.set_sectors_used(entry, true);
+        Ok(entry)
+    }
+
+    /// Writes a chunk to free sectors. It only replaces the previous version
+    /// of the chunk once `flush` is called
+    fn write_chunk(
+        &mut self,
+        slot: usize,
+        chunk: &ServerChunk,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let payload = shared::game_message_to_payload(chunk);
+        let needed_sectors =
+            (CHUNK_HEADER_SIZE + payload.len() as u64).div_ceil(SECTOR_SIZE) as u32;
+
+        let entry = self.allocate_sectors(needed_sectors)?;
+
+        let mut data = Vec::with_capacity(entry.sector_count as usize * SECTOR_SIZE as usize);
+        data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        data.extend_from_slice(&CHUNK_FORMAT_VERSION.to_le_bytes());
+        data.extend_from_slice(&payload);
+        data.resize(entry.sector_count as usize * SECTOR_SIZE as usize, 0);
+
+        self.file
+            .seek(SeekFrom::Start(entry.first_sector as u64 * SECTOR_SIZE))?;
+        self.file.write_all(&data)?;
+
+        self.pending_entries.push((slot, entry, self.index[slot]));
+
+        Ok(())
+    }
+
+    /// Makes the chunks written so far durable, then points the index to them.\
+    /// The sectors of their previous versions can only be reused after that
+    fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.sync_data()?;
+
+        for (slot, entry, _) in self.pending_entries.iter() {
+            self.file
+                .seek(SeekFrom::Start((slot * INDEX_ENTRY_SIZE) as u64))?;
+            self.file.write_all(&entry.to_bytes())?;
+            self.index[*slot] = *entry;
+        }
+
+        self.file.sync_data()?;
+
+        for (_, _, previous) in std::mem::take(&mut self.pending_entries) {
+            if !previous.is_empty() {
+                self.set_sectors_used(previous, false);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn chunk_to_region_pos(chunk_pos: &IVec3) -> IVec3 {
+    chunk_pos.div_euclid(IVec3::splat(REGION_SIZE))
+}
+
+fn chunk_slot_in_region(chunk_pos: &IVec3) -> usize {
+    let local = chunk_pos.rem_euclid(IVec3::splat(REGION_SIZE));
+    ((local.x * REGION_SIZE + local.y) * REGION_SIZE + local.z) as usize
+}
+
+fn region_file_path(region_folder: &Path, region_pos: &IVec3) -> PathBuf {
+    region_folder.join(format!(
+        "r.{}.{}.{}.{}",
+        region_pos.x, region_pos.y, region_pos.z, REGION_FILE_EXTENSION
+    ))
+}
+
+/// Writes the given chunks to their region files, opening each region only once
+pub fn save_chunks<'a>(
+    region_folder: &Path,
+    chunks: impl IntoIterator<Item = (&'a IVec3, &'a ServerChunk)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let _lock = REGION_FILES_LOCK.lock().unwrap();
+
+    fs::create_dir_all(region_folder)?;
+
+    let mut regions: HashMap<IVec3, Vec<(&IVec3, &ServerChunk)>> = HashMap::new();
+    for (chunk_pos, chunk) in chunks {
+        regions
+            .entry(chunk_to_region_pos(chunk_pos))
+            .or_default()
+            .push((chunk_pos, chunk));
+    }
+
+    for (region_pos, chunks) in regions {
+        let path = region_file_path(region_folder, &region_pos);
+        let mut region = RegionFile::open(&path, true)?.unwrap();
+
+        for (chunk_pos, chunk) in chunks {
+            region.write_chunk(chunk_slot_in_region(chunk_pos), chunk)?;
+        }
+
+        region.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Opens the region file of a chunk and reads its index entry.\
+/// `None` if the chunk was never saved. The caller must hold `REGION_FILES_LOCK`
+fn open_chunk_entry(
+    region_folder: &Path,
+    chunk_pos: &IVec3,
+) -> Result<Option<(File, IndexEntry)>, Box<dyn std::error::Error>> {
+    let path = region_file_path(region_folder, &chunk_to_region_pos(chunk_pos));
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let mut file = File::open(path)?;
+    let slot = chunk_slot_in_region(chunk_pos);
+
+    // Only read the index entry of the requested chunk instead of the whole header
+    let mut raw_entry = [0u8; INDEX_ENTRY_SIZE];
+    file.seek(SeekFrom::Start((slot * INDEX_ENTRY_SIZE) as u64))?;
+    file.read_exact(&mut raw_entry)?;
+
+    let entry = IndexEntry::from_bytes(&raw_entry);
+    if entry.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some((file, entry)))
+}
+
+/// Whether a chunk was ever saved, without reading its content
+pub fn chunk_exists(
+    region_folder: &Path,
+    chunk_pos: &IVec3,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let _lock = REGION_FILES_LOCK.lock().unwrap();
+
+    Ok(open_chunk_entry(region_folder, chunk_pos)?.is_some())
+}
+
+/// Reads a single chunk from its region file, without touching the rest of the world
+pub fn load_chunk(
+    region_folder: &Path,
+    chunk_pos: &IVec3,
+) -> Result<Option<ServerChunk>, Box<dyn std::error::Error>> {
+    let _lock = REGION_FILES_LOCK.lock().unwrap();
+
+    let Some((mut file, entry)) = open_chunk_entry(region_folder, chunk_pos)? else {
+        return Ok(None);
+    };
+
+    file.seek(SeekFrom::Start(entry.first_sector as u64 * SECTOR_SIZE))?;
+
+    let mut header = [0u8; CHUNK_HEADER_SIZE as usize];
+    file.read_exact(&mut header)?;
+    let length = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+    let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+
+    let mut payload = vec![0u8; length];
+    file.read_exact(&mut payload)?;
+
+    Ok(Some(decode_chunk(version, &payload)?))
+}
+
+/// Whether `path` is a region file, to be copied with [`copy_region_file`]
+pub fn is_region_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == REGION_FILE_EXTENSION)
+}
+
+/// Copies a region file while chunks keep being saved to it, without waiting for them.\
+/// The header is read first : chunks are only indexed once their data is written,
+/// and their previous sectors are not reused until the copy is done,
+/// so the copy holds every chunk in the state it had when the header was read
+pub fn copy_region_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    REGION_FILE_COPIES.fetch_add(1, Ordering::SeqCst);
+    let result = (|| {
+        let mut source = File::open(from)?;
+        let mut destination = File::create(to)?;
+
+        let mut header = vec![0u8; REGION_CHUNK_COUNT * INDEX_ENTRY_SIZE];
+        source.read_exact(&mut header)?;
+        destination.write_all(&header)?;
+        std::io::copy(&mut source, &mut destination)?;
+
+        Ok(())
+    })();
+    REGION_FILE_COPIES.fetch_sub(1, Ordering::SeqCst);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::world::{BlockData, BlockDirection, BlockId};
+
+    fn test_region_folder(name: &str) -> PathBuf {
+        let folder =
+            std::env::temp_dir().join(format!("region-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        folder
+    }
+
+    /// A chunk filled with `block_count` blocks, so that its payload size can vary
+    fn chunk_with_blocks(ts: u64, block_count: i32) -> ServerChunk {
+        let mut chunk = ServerChunk {
+            ts,
+            ..Default::default()
+        };
+        for i in 0..block_count {
+            let pos = IVec3::new(i % 16, (i / 16) % 16, i / 256);
+            let id = if (i * 7919) % 3 == 0 {
+                BlockId::Stone
+            } else {
+                BlockId::Dirt
+            };
+            chunk
+                .blocks
+                .insert(pos, BlockData::new(id, BlockDirection::Front));
+        }
+        chunk
+    }
+
+    fn region_path(folder: &Path) -> PathBuf {
+        region_file_path(folder, &IVec3::ZERO)
+    }
+
+    #[test]
+    fn chunks_are_saved_and_loaded() {
+        let folder = test_region_folder("save-load");
+        let chunk_pos = IVec3::new(1, 2, 3);
+
+        save_chunks(&folder, [(&chunk_pos, &chunk_with_blocks(1, 10))]).unwrap();
+        save_chunks(&folder, [(&chunk_pos, &chunk_with_blocks(2, 4096))]).unwrap();
+        save_chunks(&folder, [(&chunk_pos, &chunk_with_blocks(3, 1))]).unwrap();
+
+        assert_eq!(load_chunk(&folder, &chunk_pos).unwrap().unwrap().ts, 3);
+        assert!(load_chunk(&folder, &IVec3::new(3, 2, 1)).unwrap().is_none());
+        assert!(chunk_exists(&folder, &chunk_pos).unwrap());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn unflushed_writes_keep_the_previous_chunk() {
+        let folder = test_region_folder("unflushed");
+        let chunk_pos = IVec3::ZERO;
+
+        save_chunks(&folder, [(&chunk_pos, &chunk_with_blocks(1, 10))]).unwrap();
+
+        // Simulates a crash before the index is updated
+        let mut region = RegionFile::open(&region_path(&folder), false)
+            .unwrap()
+            .unwrap();
+        let previous = region.index[0];
+        region.write_chunk(0, &chunk_with_blocks(2, 10)).unwrap();
+        assert_ne!(
+            region.pending_entries[0].1.first_sector,
+            previous.first_sector
+        );
+        drop(region);
+
+        assert_eq!(load_chunk(&folder, &chunk_pos).unwrap().unwrap().ts, 1);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn sectors_of_replaced_chunks_are_reused() {
+        let folder = test_region_folder("reuse");
+        let chunk_pos = IVec3::ZERO;
+
+        save_chunks(&folder, [(&chunk_pos, &chunk_with_blocks(1, 10))]).unwrap();
+        save_chunks(&folder, [(&chunk_pos, &chunk_with_blocks(2, 10))]).unwrap();
+        let file_len = fs::metadata(region_path(&folder)).unwrap().len();
+
+        for ts in 3..10 {
+            save_chunks(&folder, [(&chunk_pos, &chunk_with_blocks(ts, 10))]).unwrap();
+        }
+
+        assert_eq!(fs::metadata(region_path(&folder)).unwrap().len(), file_len);
+        assert_eq!(load_chunk(&folder, &chunk_pos).unwrap().unwrap().ts, 9);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
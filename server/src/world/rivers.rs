@@ -0,0 +1,99 @@
+use noise::{NoiseFn, Perlin};
+use shared::world::BlockId;
+
+use super::carving::SEA_LEVEL;
+
+const RIVER_SCALE: f64 = 0.0025;
+/// Rivers flow where the river noise is closer to 0 than this value
+const RIVER_HALF_WIDTH: f64 = 0.015;
+/// Banks slope down to the river from this distance to 0
+const RIVER_VALLEY_HALF_WIDTH: f64 = 0.05;
+const RIVER_DEPTH: i32 = 3;
+
+const LAKE_SCALE: f64 = 0.008;
+/// Basins start where the lake noise goes above this value, and reach their bottom at `LAKE_FULL`
+const LAKE_THRESHOLD: f64 = 0.4;
+const LAKE_FULL: f64 = 0.55;
+const LAKE_DEPTH: i32 = 5;
+/// Lakes fade out in columns higher than this above sea level, so that they stay in low lands
+const LAKE_MAX_HEIGHT: f64 = 8.;
+
+/// Columns up to this height above the water are covered with sand
+const SHORE_HEIGHT: i32 = 1;
+
+/// Column after rivers and lakes were dug into it
+pub struct ShapedColumn {
+    pub terrain_height: i32,
+    /// Surface and sub-surface blocks replacing those of the biome, along rivers and lakes
+    pub shore_blocks: Option<(BlockId, BlockId)>,
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+/// Lowers the heightmap along a river network and in a few basins.\
+/// Dug columns go below sea level, where the generation fills them with water
+pub struct WaterShaper {
+    river: Perlin,
+    lake: Perlin,
+}
+
+impl WaterShaper {
+    pub fn new(seed: u32) -> Self {
+        // The previous seeds are used by the heightmap, the biomes and the carver
+        Self {
+            river: Perlin::new(seed.wrapping_add(8)),
+            lake: Perlin::new(seed.wrapping_add(9)),
+        }
+    }
+
+    pub fn shape_column(&self, x: i32, z: i32, terrain_height: i32) -> ShapedColumn {
+        let unchanged = ShapedColumn {
+            terrain_height,
+            shore_blocks: None,
+        };
+
+        // Oceans are already under water
+        if terrain_height <= SEA_LEVEL {
+            return unchanged;
+        }
+
+        let (fx, fz) = (x as f64, z as f64);
+
+        // Rivers follow the places where the noise crosses 0, which form a network of lines
+        let river = self.river.get([fx * RIVER_SCALE, fz * RIVER_SCALE]).abs();
+        let river_factor = 1. - smoothstep(RIVER_HALF_WIDTH, RIVER_VALLEY_HALF_WIDTH, river);
+
+        let lake = self.lake.get([fx * LAKE_SCALE, fz * LAKE_SCALE]);
+        let lowland = 1. - ((terrain_height - SEA_LEVEL) as f64 / LAKE_MAX_HEIGHT).clamp(0., 1.);
+        let lake_factor = smoothstep(LAKE_THRESHOLD, LAKE_FULL, lake) * lowland;
+
+        let (factor, bed_height, bed_block) = if river_factor >= lake_factor {
+            (river_factor, SEA_LEVEL - RIVER_DEPTH, BlockId::Gravel)
+        } else {
+            (lake_factor, SEA_LEVEL - LAKE_DEPTH, BlockId::Sand)
+        };
+
+        if factor <= 0. {
+            return unchanged;
+        }
+
+        let height = terrain_height as f64 + (bed_height - terrain_height) as f64 * factor;
+        let terrain_height = height.round() as i32;
+
+        let shore_blocks = if terrain_height <= SEA_LEVEL {
+            Some((bed_block, BlockId::Sand))
+        } else if terrain_height <= SEA_LEVEL + SHORE_HEIGHT {
+            Some((BlockId::Sand, BlockId::Sand))
+        } else {
+            None
+        };
+
+        ShapedColumn {
+            terrain_height,
+            shore_blocks,
+        }
+    }
+}
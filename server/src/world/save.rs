@@ -2,94 +2,206 @@ use crate::init::ServerTime;
 use bevy::prelude::*;
 use ron::ser::PrettyConfig;
 use shared::messages::PlayerId;
-use shared::players::Player;
+use shared::messages::PlayerSave;
 use shared::world::MobId;
-use shared::world::ServerChunk;
+use shared::world::PendingBlocks;
 use shared::world::ServerItemStack;
 use shared::world::ServerMob;
 use shared::world::ServerWorldMap;
+use shared::world::WorldGeneratorConfig;
 use shared::world::WorldSeed;
-use shared::GameFolderPaths;
+use shared::{GameFolderPaths, GameServerConfig, TICKS_PER_SECOND};
 use std::collections::HashMap;
-use std::{fs::File, io::Write, path::Path};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
 
 #[derive(Event)]
 pub enum SaveRequestEvent {
     World,
     Player(PlayerId),
+    /// Player which was already removed from the world, with its name
+    PlayerLeft(PlayerId, String, PlayerSave),
 }
 
-use crate::world::data::SAVE_PATH;
+use crate::world::data::{get_player_save_path, get_region_folder_path, SAVE_PATH};
+use crate::world::generator::ActiveWorldGenerator;
+use crate::world::migration::{to_versioned_ron, ServerChunkV1};
+use crate::world::writer::{WorldWriter, WriteId};
 
 #[derive(serde::Serialize, serde::Deserialize, Default)]
 pub struct WorldData {
-    pub map: HashMap<IVec3, ServerChunk>,
+    /// Chunks are stored in region files, this field is only read from legacy saves
+    #[serde(default, skip_serializing)]
+    pub map: HashMap<IVec3, ServerChunkV1>,
     pub mobs: HashMap<MobId, ServerMob>,
     pub seed: WorldSeed,
     pub name: String,
     pub time: u64,
     pub item_stacks: Vec<ServerItemStack>,
+    /// Parts of structures waiting for their chunk to be generated
+    #[serde(default)]
+    pub pending_blocks: PendingBlocks,
+    /// Worlds saved before generators could be chosen all used the noise generator
+    #[serde(default)]
+    pub generator: WorldGeneratorConfig,
 }
 
-pub fn save_world_system(
+/// World save requested while the previous one is still being written.\
+/// Requests are merged into a single save, done once the previous one is on disk
+#[derive(Resource, Default)]
+pub struct PendingSave {
+    requested: bool,
+    /// Write of the last world save
+    last_world_write: Option<WriteId>,
+}
+
+pub fn autosave_system(
+    time: Res<ServerTime>,
+    config: Res<GameServerConfig>,
     world_map: Res<ServerWorldMap>,
+    mut ev_save_request: EventWriter<SaveRequestEvent>,
+) {
+    if config.autosave_interval_secs == 0 || time.0 == 0 {
+        return;
+    }
+
+    if !time
+        .0
+        .is_multiple_of(config.autosave_interval_secs * TICKS_PER_SECOND)
+    {
+        return;
+    }
+
+    info!("[{}] Autosaving world", world_map.name);
+
+    ev_save_request.write(SaveRequestEvent::World);
+    for id in world_map.players.keys() {
+        ev_save_request.write(SaveRequestEvent::Player(*id));
+    }
+}
+
+/// Queues the requested saves on the [`WorldWriter`], so that serialization and disk writes
+/// happen on the IO task pool and in order with the other chunk writes
+pub fn save_world_system(
+    mut world_map: ResMut<ServerWorldMap>,
     world_seed: Res<WorldSeed>,
+    generator: Res<ActiveWorldGenerator>,
     game_folder_path: Res<GameFolderPaths>,
     time: Res<ServerTime>,
     mut event: EventReader<SaveRequestEvent>,
+    mut pending_save: ResMut<PendingSave>,
+    mut writer: ResMut<WorldWriter>,
 ) {
     // Reads all events to prevent them from being queued forever and repeatedly request a save
-    let mut save_requested = false;
+    let mut players_to_save: Vec<(PlayerId, PlayerSave, String)> = Vec::new();
     for ev in event.read() {
-        save_requested = true;
-
-        if let SaveRequestEvent::Player(id) = ev {
-            if let Some(player) = world_map.players.get(id) {
-                // define save file path
-                let save_file_path = format!(
-                    "{}{}/players/{}.ron",
-                    game_folder_path.game_folder_path.join(SAVE_PATH).display(),
-                    world_map.name,
-                    id
-                );
-
-                if let Err(err) = save_player_data(player, &save_file_path) {
+        let (id, name, player_save) = match ev {
+            SaveRequestEvent::World => {
+                pending_save.requested = true;
+                continue;
+            }
+            SaveRequestEvent::Player(id) => match world_map.players.get(id) {
+                Some(player) => (id, &player.name, player.to_save()),
+                None => continue,
+            },
+            SaveRequestEvent::PlayerLeft(id, name, player_save) => (id, name, player_save.clone()),
+        };
+
+        // define save file path
+        let save_file_path = get_player_save_path(&game_folder_path, &world_map.name, name)
+            .display()
+            .to_string();
+
+        players_to_save.push((*id, player_save, save_file_path));
+    }
+
+    let world_name = world_map.name.clone();
+
+    // Player saves only write the player files
+    if !players_to_save.is_empty() {
+        let world_name = world_name.clone();
+        writer.queue(move || {
+            for (id, player_save, save_file_path) in players_to_save {
+                if let Err(err) = save_player_data(&player_save, &save_file_path) {
                     error!(
                         "[{}] Could not save data for player {} : {}",
-                        world_map.name, id, err
+                        world_name, id, err
                     );
                 } else {
-                    info!("[{}] Player {} data saved successfully", world_map.name, id);
+                    info!("[{}] Player {} data saved successfully", world_name, id);
                 }
             }
-        }
+        });
     }
 
-    // If a save was requested by the user
-    if save_requested {
-        let world_data = WorldData {
-            map: world_map.chunks.map.clone(),
-            mobs: world_map.mobs.clone(),
-            item_stacks: world_map.item_stacks.clone(),
-            name: world_map.name.clone(),
-            seed: *world_seed,
-            time: time.0,
-        };
+    if !pending_save.requested
+        || pending_save
+            .last_world_write
+            .is_some_and(|id| !writer.is_done(id))
+    {
+        return;
+    }
+    pending_save.requested = false;
 
-        // define save file path
-        let save_file_path = format!(
-            "{}{}/world.ron",
-            game_folder_path.game_folder_path.join(SAVE_PATH).display(),
-            world_map.name
-        );
-
-        // save seed and world data
-        if let Err(e) = save_world_data(&world_data, &save_file_path) {
-            error!("Failed to save world data: {}", e);
-        } else {
-            info!("World data saved successfully! Name: {}", world_map.name);
-        }
+    let world_data = WorldData {
+        map: HashMap::new(),
+        mobs: world_map.mobs.clone(),
+        item_stacks: world_map.item_stacks.clone(),
+        pending_blocks: world_map.chunks.pending_blocks.clone(),
+        name: world_map.name.clone(),
+        seed: *world_seed,
+        time: time.0,
+        generator: generator.config.clone(),
+    };
+
+    // define save file path
+    let save_file_path = format!(
+        "{}{}/world.ron",
+        game_folder_path.game_folder_path.join(SAVE_PATH).display(),
+        world_map.name
+    );
+
+    let region_folder = get_region_folder_path(&game_folder_path, &world_map.name);
+
+    // Only the chunks changed since they were last written.
+    // They are saved first, so that world data never references missing chunks
+    let changed_chunks: Vec<IVec3> = world_map.chunks.changed_chunks.iter().copied().collect();
+    pending_save.last_world_write = Some(writer.queue_chunks(
+        &mut world_map.chunks,
+        changed_chunks,
+        region_folder,
+        move || {
+            if let Err(e) = save_world_data(&world_data, &save_file_path) {
+                error!("Failed to save world data: {}", e);
+            } else {
+                info!("World data saved successfully! Name: {}", world_name);
+            }
+        },
+    ));
+}
+
+/// Writes the contents to a temporary file which is then renamed over the destination,
+/// so that a crash in the middle of a save never leaves a truncated file behind
+pub fn write_file_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+
+    fs::rename(&tmp_path, path)?;
+
+    // Make sure the rename itself is persisted
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        File::open(parent)?.sync_all()?;
     }
+
+    Ok(())
 }
 
 pub fn save_world_data(
@@ -98,34 +210,30 @@ pub fn save_world_data(
 ) -> Result<(), Box<dyn std::error::Error>> {
     // configure RON serialization
     let pretty_config = PrettyConfig::new()
-        .with_depth_limit(3)
+        .with_depth_limit(4)
         .with_separate_tuple_members(true)
         .with_enumerate_arrays(true);
 
-    // serialize combined data (map + seed)
-    let serialized = ron::ser::to_string_pretty(world_data, pretty_config)?;
-    let path = Path::new(file_path);
-    let mut file = File::create(path)?;
-    file.write_all(serialized.as_bytes())?;
+    // serialize world metadata (seed, mobs, time...), chunks are saved separately
+    let serialized = to_versioned_ron(world_data, pretty_config)?;
+    write_file_atomically(Path::new(file_path), serialized.as_bytes())?;
     info!("World data saved to {}", file_path);
     Ok(())
 }
 
 pub fn save_player_data(
-    player: &Player,
+    player_save: &PlayerSave,
     file_path: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // RON Serialization config
     let pretty_config = PrettyConfig::new()
-        .with_depth_limit(3)
+        .with_depth_limit(4)
         .with_separate_tuple_members(true)
         .with_enumerate_arrays(true);
 
     // Serialize Complete player data
-    let serialized = ron::ser::to_string_pretty(player, pretty_config)?;
-    let path = Path::new(&file_path);
-    let mut file = File::create(path)?;
-    file.write_all(serialized.as_bytes())?;
+    let serialized = to_versioned_ron(player_save, pretty_config)?;
+    write_file_atomically(Path::new(&file_path), serialized.as_bytes())?;
 
     Ok(())
 }
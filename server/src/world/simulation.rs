@@ -4,14 +4,16 @@ use bevy::{
 };
 use bevy_renet::renet::{ClientId, RenetServer};
 use shared::{
-    messages::{NetworkAction, PlayerFrameInput, PlayerUpdateEvent},
-    players::{simulation::simulate_player_actions, blocks::CallerType},
-    world::{ServerWorldMap, WorldSeed},
+    messages::{
+        NetworkAction, PlayerDespawnEvent, PlayerFrameInput, PlayerSpawnEvent, PlayerUpdateEvent,
+        ServerToClientMessage,
+    },
+    players::{blocks::CallerType, simulation::simulate_player_actions},
+    world::{world_position_to_chunk_position, ServerWorldMap},
 };
 
-use crate::{network::extensions::SendGameMessageExtension, world::generation::generate_chunk};
-
-use super::broadcast_world::get_all_active_chunks;
+use crate::network::extensions::SendGameMessageExtension;
+use crate::world::view::PlayerViews;
 
 #[derive(Event, Debug)]
 pub struct PlayerInputsEvent {
@@ -23,45 +25,83 @@ pub fn handle_player_inputs_system(
     mut events: EventReader<PlayerInputsEvent>,
     mut world_map: ResMut<ServerWorldMap>,
     mut server: ResMut<RenetServer>,
-    seed: Res<WorldSeed>,
+    mut views: ResMut<PlayerViews>,
 ) {
     let world_map = world_map.as_mut();
     let players = &mut world_map.players;
     let chunks = &mut world_map.chunks;
 
-    let active_chunks = get_all_active_chunks(players, 1);
-    for c in active_chunks {
-        let chunk = chunks.map.get(&c);
-
-        if chunk.is_none() {
-            let chunk = generate_chunk(c, seed.0);
-            info!("Generated chunk: {:?}", c);
-            chunks.map.insert(c, chunk);
-        }
-    }
-
     let mut player_actions = HashMap::<u64, HashSet<NetworkAction>>::new();
     for client_id in players.keys() {
         player_actions.insert(*client_id, HashSet::new());
     }
 
     for ev in events.read() {
-        let player = players.get_mut(&ev.client_id).unwrap();
+        // The player may have left since the inputs were received
+        let Some(player) = players.get_mut(&ev.client_id) else {
+            continue;
+        };
+
+        // Chunks are generated in the background, players wait until the one they are in is ready
+        // instead of falling through it
+        if !chunks
+            .map
+            .contains_key(&world_position_to_chunk_position(player.position))
+        {
+            continue;
+        }
 
         simulate_player_actions(player, chunks, &ev.input.clone(), CallerType::Server);
 
         player.last_input_processed = ev.input.time_ms;
     }
 
-    for player in players.values() {
-        server.broadcast_game_message(shared::messages::ServerToClientMessage::PlayerUpdate(
-            PlayerUpdateEvent {
-                id: player.id,
-                position: player.position,
-                orientation: player.camera_transform.rotation,
-                last_ack_time: player.last_input_processed,
-                inventory: player.inventory.clone(),
-            },
-        ));
+    for client_id in server.clients_id() {
+        let Some(receiver) = players.get(&client_id) else {
+            continue;
+        };
+        let view = views.get_or_default(client_id);
+
+        // Players always receive their own state, which acknowledges their inputs,
+        // and only the state of the players around them
+        for player in players.values() {
+            if player.id != receiver.id {
+                let visible = view.can_see(receiver.position, player.position);
+
+                // Players are despawned when they leave the view, so they are not left
+                // frozen where they were last seen
+                if visible && view.visible_players.insert(player.id) {
+                    server.send_game_message(
+                        client_id,
+                        ServerToClientMessage::PlayerSpawn(PlayerSpawnEvent {
+                            id: player.id,
+                            name: player.name.clone(),
+                            data: player.to_save(),
+                        }),
+                    );
+                } else if !visible {
+                    if view.visible_players.remove(&player.id) {
+                        server.send_game_message(
+                            client_id,
+                            ServerToClientMessage::PlayerDespawn(PlayerDespawnEvent {
+                                id: player.id,
+                            }),
+                        );
+                    }
+                    continue;
+                }
+            }
+
+            server.send_game_message(
+                client_id,
+                ServerToClientMessage::PlayerUpdate(PlayerUpdateEvent {
+                    id: player.id,
+                    position: player.position,
+                    orientation: player.camera_transform.rotation,
+                    last_ack_time: player.last_input_processed,
+                    inventory: player.inventory.clone(),
+                }),
+            );
+        }
     }
 }
@@ -0,0 +1,176 @@
+use bevy::prelude::*;
+use shared::world::{
+    to_global_pos, BlockData, PendingBlocks, ServerChunk, ServerChunkWorldMap, ServerWorldMap,
+};
+use shared::{GameFolderPaths, TICKS_PER_SECOND};
+use std::collections::{HashMap, HashSet};
+
+use crate::init::ServerTime;
+use crate::world::data::get_region_folder_path;
+use crate::world::generation::GeneratedChunk;
+use crate::world::writer::WorldWriter;
+
+use super::view::{get_all_active_chunks, PlayerViews};
+
+/// Number of ticks a chunk can stay out of every player's range before being unloaded
+pub const CHUNK_UNLOAD_GRACE_TICKS: u64 = 30 * TICKS_PER_SECOND;
+
+/// Last tick at which each loaded chunk was in range of at least one player
+#[derive(Resource, Default, Debug)]
+pub struct ChunkActivity {
+    pub last_active_tick: HashMap<IVec3, u64>,
+    /// Chunks unloaded without waiting for the grace period, unless a player gets close
+    pub released: HashSet<IVec3>,
+}
+
+/// Adds a freshly generated chunk to the loaded chunks, merging the structures
+/// crossing its borders with its neighbours
+pub fn insert_generated_chunk(
+    chunks: &mut ServerChunkWorldMap,
+    chunk_pos: IVec3,
+    generated: GeneratedChunk,
+) {
+    let mut chunk = generated.chunk;
+    // Structures of neighbours generated earlier may reach into this chunk
+    if let Some(blocks) = chunks.pending_blocks.remove(&chunk_pos) {
+        place_structure_blocks(&mut chunk, blocks);
+    }
+
+    chunks.map.insert(chunk_pos, chunk);
+    chunks.changed_chunks.insert(chunk_pos);
+    spill_structure_blocks(chunks, generated.spilled_blocks);
+}
+
+/// Adds a chunk read from disk to the loaded chunks, with the parts of structures
+/// generated next to it while it was not loaded
+pub fn insert_loaded_chunk(chunks: &mut ServerChunkWorldMap, chunk_pos: IVec3, chunk: ServerChunk) {
+    let mut chunk = chunk;
+    if let Some(blocks) = chunks.pending_blocks.remove(&chunk_pos) {
+        place_structure_blocks(&mut chunk, blocks);
+        chunks.changed_chunks.insert(chunk_pos);
+    }
+
+    chunks.map.insert(chunk_pos, chunk);
+}
+
+/// Writes the parts of structures coming from a neighbouring chunk.\
+/// Existing blocks are never replaced, so that the result is the same
+/// whichever of the two chunks was generated first.\
+/// Returns the local positions of the blocks which were placed
+fn place_structure_blocks(chunk: &mut ServerChunk, blocks: Vec<(IVec3, BlockData)>) -> Vec<IVec3> {
+    let mut placed = Vec::new();
+    for (local_pos, block) in blocks {
+        if !chunk.blocks.contains_key(&local_pos) {
+            chunk.blocks.insert(local_pos, block);
+            placed.push(local_pos);
+        }
+    }
+    placed
+}
+
+/// Sends the blocks of structures crossing the borders of a freshly generated chunk
+/// to the neighbouring chunks, or keeps them until these chunks are loaded
+fn spill_structure_blocks(chunks: &mut ServerChunkWorldMap, spilled_blocks: PendingBlocks) {
+    for (chunk_pos, blocks) in spilled_blocks {
+        let Some(chunk) = chunks.map.get_mut(&chunk_pos) else {
+            // Merged once the chunk is generated or read from disk, never on the tick
+            chunks
+                .pending_blocks
+                .entry(chunk_pos)
+                .or_default()
+                .extend(blocks);
+            continue;
+        };
+
+        // Clients may already have this chunk
+        let placed = place_structure_blocks(chunk, blocks);
+        if !placed.is_empty() {
+            chunks.changed_chunks.insert(chunk_pos);
+        }
+        for local_pos in placed {
+            chunks
+                .updated_blocks
+                .push(to_global_pos(&chunk_pos, &local_pos));
+        }
+    }
+}
+
+/// Unloads the chunks out of every player's range for a while.\
+/// Changed chunks are written on the IO task pool first, and stay loaded
+/// until every write holding a copy of them is done
+pub fn unload_inactive_chunks_system(
+    mut world_map: ResMut<ServerWorldMap>,
+    mut activity: ResMut<ChunkActivity>,
+    time: Res<ServerTime>,
+    game_folder_paths: Res<GameFolderPaths>,
+    views: Res<PlayerViews>,
+    mut writer: ResMut<WorldWriter>,
+) {
+    // No need to check every tick
+    if !time.0.is_multiple_of(TICKS_PER_SECOND) {
+        return;
+    }
+
+    let world_map = world_map.as_mut();
+
+    // Clients are never left with a chunk the server could change without telling them
+    let active_chunks: HashSet<IVec3> = get_all_active_chunks(&world_map.players, &views)
+        .into_iter()
+        .chain(views.loaded_chunks().copied())
+        .collect();
+
+    for chunk_pos in active_chunks.iter() {
+        activity.last_active_tick.insert(*chunk_pos, time.0);
+        activity.released.remove(chunk_pos);
+    }
+
+    let chunks_to_unload: Vec<IVec3> = world_map
+        .chunks
+        .map
+        .keys()
+        .filter(|chunk_pos| {
+            if activity.released.contains(*chunk_pos) {
+                return true;
+            }
+            let last_active_tick = *activity
+                .last_active_tick
+                .entry(**chunk_pos)
+                .or_insert(time.0);
+            time.0 - last_active_tick > CHUNK_UNLOAD_GRACE_TICKS
+        })
+        .copied()
+        .collect();
+
+    if chunks_to_unload.is_empty() {
+        return;
+    }
+
+    let region_folder = get_region_folder_path(&game_folder_paths, &world_map.name);
+    let changed: Vec<IVec3> = chunks_to_unload
+        .iter()
+        .filter(|chunk_pos| world_map.chunks.changed_chunks.contains(chunk_pos))
+        .copied()
+        .collect();
+    if !changed.is_empty() {
+        writer.queue_chunks(&mut world_map.chunks, changed, region_folder, || {});
+    }
+
+    // Chunks still being written are checked again on a later pass
+    let mut unloaded = 0;
+    for chunk_pos in chunks_to_unload.iter() {
+        if writer.is_chunk_written(chunk_pos) {
+            world_map.chunks.map.remove(chunk_pos);
+            activity.last_active_tick.remove(chunk_pos);
+            activity.released.remove(chunk_pos);
+            unloaded += 1;
+        }
+    }
+
+    if unloaded > 0 {
+        debug!(
+            "Unloaded {} chunks, {} chunks still loaded",
+            unloaded,
+            world_map.chunks.map.len()
+        );
+    }
+}
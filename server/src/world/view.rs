@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+use shared::messages::PlayerId;
+use shared::players::Player;
+use shared::world::world_position_to_chunk_position;
+use shared::CHUNK_SIZE;
+use std::collections::{HashMap, HashSet};
+
+/// View distance of the players which did not ask for one yet, in chunks
+pub const DEFAULT_VIEW_DISTANCE: u32 = 2;
+
+/// Number of chunk layers loaded above and below players, whatever their view distance.\
+/// The terrain never spans many layers, so there is no point in going further
+const MAX_VERTICAL_VIEW_DISTANCE: i32 = 3;
+
+/// What a player can see of the world
+pub struct PlayerView {
+    /// Horizontal view distance, in chunks, already capped by the server
+    pub view_distance: u32,
+    /// Chunks sent to the client and not unloaded since
+    pub loaded_chunks: HashSet<IVec3>,
+    /// Other players spawned on the client and not despawned since
+    pub visible_players: HashSet<PlayerId>,
+}
+
+impl Default for PlayerView {
+    fn default() -> Self {
+        Self {
+            view_distance: DEFAULT_VIEW_DISTANCE,
+            loaded_chunks: HashSet::new(),
+            visible_players: HashSet::new(),
+        }
+    }
+}
+
+impl PlayerView {
+    /// Chunks in view of a player standing in `player_chunk`, closest first
+    pub fn chunks_in_range(&self, player_chunk: IVec3) -> Vec<IVec3> {
+        let distance = self.view_distance as i32;
+        get_player_nearby_chunks_coords(
+            player_chunk,
+            distance,
+            distance.min(MAX_VERTICAL_VIEW_DISTANCE),
+        )
+    }
+
+    /// Whether an entity at `position` is close enough to a player at `player_position`
+    /// for the client to be told about it
+    pub fn can_see(&self, player_position: Vec3, position: Vec3) -> bool {
+        player_position.distance(position) < (self.view_distance as i32 * CHUNK_SIZE) as f32
+    }
+
+    /// Whether a loaded chunk should be unloaded from the client.\
+    /// Chunks are kept one chunk past the view distance, so that walking back and forth
+    /// across a chunk border does not send the same chunks over and over
+    pub fn is_out_of_range(&self, player_chunk: IVec3, chunk_pos: IVec3) -> bool {
+        let distance = self.view_distance as i32 + 1;
+        let offset = (chunk_pos - player_chunk).abs();
+        offset.x > distance
+            || offset.z > distance
+            || offset.y > distance.min(MAX_VERTICAL_VIEW_DISTANCE + 1)
+    }
+}
+
+/// View of every connected player, kept by the server only
+#[derive(Resource, Default)]
+pub struct PlayerViews {
+    views: HashMap<PlayerId, PlayerView>,
+}
+
+impl PlayerViews {
+    pub fn get(&self, player_id: &PlayerId) -> Option<&PlayerView> {
+        self.views.get(player_id)
+    }
+
+    /// View of a player, created with the default view distance if it is not known yet
+    pub fn get_or_default(&mut self, player_id: PlayerId) -> &mut PlayerView {
+        self.views.entry(player_id).or_default()
+    }
+
+    /// Forgets a player who left, both its own view and the clients it was spawned on
+    pub fn remove(&mut self, player_id: &PlayerId) {
+        self.views.remove(player_id);
+        for view in self.views.values_mut() {
+            view.visible_players.remove(player_id);
+        }
+    }
+
+    /// Every chunk held by at least one client, possibly more than once
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = &IVec3> {
+        self.views
+            .values()
+            .flat_map(|view| view.loaded_chunks.iter())
+    }
+}
+
+/// Chunks in view of at least one player, each player with its own view distance
+pub fn get_all_active_chunks(
+    players: &HashMap<PlayerId, Player>,
+    views: &PlayerViews,
+) -> Vec<IVec3> {
+    let default_view = PlayerView::default();
+    let mut seen = HashSet::new();
+
+    players
+        .values()
+        .flat_map(|player| {
+            views
+                .get(&player.id)
+                .unwrap_or(&default_view)
+                .chunks_in_range(world_position_to_chunk_position(player.position))
+        })
+        .filter(|chunk_pos| seen.insert(*chunk_pos))
+        .collect()
+}
+
+fn get_player_nearby_chunks_coords(
+    player_chunk_position: IVec3,
+    render_distance: i32,
+    vertical_distance: i32,
+) -> Vec<IVec3> {
+    let mut chunks: Vec<IVec3> = Vec::new();
+    for x in -render_distance..=render_distance {
+        for y in -vertical_distance..=vertical_distance {
+            for z in -render_distance..=render_distance {
+                chunks.push(player_chunk_position + IVec3::new(x, y, z));
+            }
+        }
+    }
+
+    // let's sort by distance to player
+    chunks.sort_by_key(|&c| (c - player_chunk_position).length_squared());
+
+    chunks
+}
@@ -0,0 +1,239 @@
+use bevy::prelude::*;
+use bevy::tasks::{block_on, IoTaskPool, Task};
+use shared::world::{ServerChunk, ServerChunkWorldMap, ServerWorldMap};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::world::region::save_chunks;
+
+/// Identifies a write queued in the [`WorldWriter`], writes are numbered from 1 in order
+pub type WriteId = u64;
+
+#[derive(Default)]
+struct WriteProgress {
+    /// Last write done, as writes finish in order
+    done: WriteId,
+    /// Chunks whose write failed since the last poll
+    failed_chunks: Vec<IVec3>,
+}
+
+/// Runs the writes to the world folder on the IO task pool, one after the other
+/// in the order they were queued, so that an older copy of a chunk never lands
+/// over a newer one. The tick never waits for them
+#[derive(Resource, Default)]
+pub struct WorldWriter {
+    /// Last write queued, the next one only starts once it is done
+    last_write: Option<Task<()>>,
+    queued: WriteId,
+    progress: Arc<Mutex<WriteProgress>>,
+    /// Last write done as of the last poll, failed chunks up to it are marked as changed again
+    done: WriteId,
+    /// Last write holding a copy of each chunk, until it is done
+    chunk_writes: HashMap<IVec3, WriteId>,
+}
+
+impl WorldWriter {
+    /// Queues a write, which starts once every write queued before it is done
+    pub fn queue(&mut self, write: impl FnOnce() + Send + 'static) -> WriteId {
+        self.queued += 1;
+        let id = self.queued;
+        let previous = self.last_write.take();
+        let progress = Arc::clone(&self.progress);
+
+        self.last_write = Some(IoTaskPool::get().spawn(async move {
+            if let Some(previous) = previous {
+                previous.await;
+            }
+            write();
+            progress.lock().unwrap().done = id;
+        }));
+
+        id
+    }
+
+    /// Queues a write of the changed chunks among `positions`, which are then no longer
+    /// considered changed. `then` runs right after them, only if they were all written
+    pub fn queue_chunks(
+        &mut self,
+        chunks: &mut ServerChunkWorldMap,
+        positions: impl IntoIterator<Item = IVec3>,
+        region_folder: PathBuf,
+        then: impl FnOnce() + Send + 'static,
+    ) -> WriteId {
+        let snapshot: Vec<(IVec3, ServerChunk)> = positions
+            .into_iter()
+            .filter(|chunk_pos| chunks.changed_chunks.remove(chunk_pos))
+            .filter_map(|chunk_pos| Some((chunk_pos, chunks.map.get(&chunk_pos)?.clone())))
+            .collect();
+
+        let id = self.queued + 1;
+        for (chunk_pos, _) in snapshot.iter() {
+            self.chunk_writes.insert(*chunk_pos, id);
+        }
+
+        let progress = Arc::clone(&self.progress);
+        self.queue(move || {
+            match save_chunks(
+                &region_folder,
+                snapshot.iter().map(|(chunk_pos, chunk)| (chunk_pos, chunk)),
+            ) {
+                Ok(()) => then(),
+                Err(err) => {
+                    error!("Could not save {} chunks : {}", snapshot.len(), err);
+                    progress
+                        .lock()
+                        .unwrap()
+                        .failed_chunks
+                        .extend(snapshot.iter().map(|(chunk_pos, _)| *chunk_pos));
+                }
+            }
+        })
+    }
+
+    /// Whether a write was done as of the last poll
+    pub fn is_done(&self, id: WriteId) -> bool {
+        id <= self.done
+    }
+
+    /// Whether no write holding a copy of the chunk is still queued or running
+    pub fn is_chunk_written(&self, chunk_pos: &IVec3) -> bool {
+        !self.chunk_writes.contains_key(chunk_pos)
+    }
+
+    /// Forgets the writes which are done. The chunks they could not write
+    /// are marked as changed again, so that they are neither lost nor unloaded
+    pub fn poll(&mut self, chunks: &mut ServerChunkWorldMap) {
+        let mut progress = self.progress.lock().unwrap();
+        self.done = progress.done;
+        for chunk_pos in progress.failed_chunks.drain(..) {
+            if chunks.map.contains_key(&chunk_pos) {
+                chunks.changed_chunks.insert(chunk_pos);
+            }
+        }
+        drop(progress);
+
+        let done = self.done;
+        self.chunk_writes.retain(|_, id| *id > done);
+    }
+
+    /// Waits for every queued write
+    pub fn flush(&mut self) {
+        if let Some(last_write) = self.last_write.take() {
+            block_on(last_write);
+        }
+    }
+}
+
+pub fn poll_world_writes_system(
+    mut writer: ResMut<WorldWriter>,
+    mut world_map: ResMut<ServerWorldMap>,
+) {
+    writer.poll(&mut world_map.chunks);
+}
+
+/// Makes sure every write is done before the server shuts down
+pub fn flush_world_writes_on_exit(
+    mut ev_app_exit: EventReader<AppExit>,
+    mut writer: ResMut<WorldWriter>,
+) {
+    if ev_app_exit.read().next().is_none() {
+        return;
+    }
+
+    info!("Waiting for the last writes to finish before exiting");
+    writer.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::region::load_chunk;
+    use bevy::tasks::TaskPool;
+    use std::fs;
+
+    fn test_folder(name: &str) -> PathBuf {
+        let folder =
+            std::env::temp_dir().join(format!("writer-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        folder
+    }
+
+    fn change_chunk(chunks: &mut ServerChunkWorldMap, chunk_pos: IVec3, ts: u64) {
+        chunks.map.insert(
+            chunk_pos,
+            ServerChunk {
+                ts,
+                ..Default::default()
+            },
+        );
+        chunks.changed_chunks.insert(chunk_pos);
+    }
+
+    #[test]
+    fn chunk_writes_land_in_order() {
+        IoTaskPool::get_or_init(TaskPool::new);
+        let folder = test_folder("order");
+        let mut writer = WorldWriter::default();
+        let mut chunks = ServerChunkWorldMap::default();
+        let chunk_pos = IVec3::new(1, 0, -1);
+
+        for ts in 1..=20 {
+            change_chunk(&mut chunks, chunk_pos, ts);
+            writer.queue_chunks(&mut chunks, [chunk_pos], folder.clone(), || {});
+        }
+        assert!(chunks.changed_chunks.is_empty());
+        assert!(!writer.is_chunk_written(&chunk_pos));
+
+        writer.flush();
+        writer.poll(&mut chunks);
+
+        assert!(writer.is_chunk_written(&chunk_pos));
+        assert!(writer.is_done(20));
+        assert_eq!(load_chunk(&folder, &chunk_pos).unwrap().unwrap().ts, 20);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn unchanged_chunks_are_not_written() {
+        IoTaskPool::get_or_init(TaskPool::new);
+        let folder = test_folder("unchanged");
+        let mut writer = WorldWriter::default();
+        let mut chunks = ServerChunkWorldMap::default();
+        let chunk_pos = IVec3::ZERO;
+
+        change_chunk(&mut chunks, chunk_pos, 1);
+        chunks.changed_chunks.clear();
+        writer.queue_chunks(&mut chunks, [chunk_pos], folder.clone(), || {});
+
+        assert!(writer.is_chunk_written(&chunk_pos));
+        writer.flush();
+        assert!(load_chunk(&folder, &chunk_pos).unwrap().is_none());
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn failed_chunk_writes_are_changed_again() {
+        IoTaskPool::get_or_init(TaskPool::new);
+        // A file where the region folder should be, so that every write fails
+        let folder = test_folder("failed");
+        fs::write(&folder, "").unwrap();
+        let mut writer = WorldWriter::default();
+        let mut chunks = ServerChunkWorldMap::default();
+        let chunk_pos = IVec3::ZERO;
+
+        change_chunk(&mut chunks, chunk_pos, 1);
+        writer.queue_chunks(&mut chunks, [chunk_pos], folder.clone(), || {
+            panic!("chunks were not written")
+        });
+        writer.flush();
+        writer.poll(&mut chunks);
+
+        assert!(writer.is_chunk_written(&chunk_pos));
+        assert!(chunks.changed_chunks.contains(&chunk_pos));
+
+        fs::remove_file(&folder).unwrap();
+    }
+}
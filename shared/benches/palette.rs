@@ -0,0 +1,160 @@
+//! Compares the paletted chunks with the chunks they replaced, which stored
+//! every non-air block in a `HashMap`.
+//!
+//! Run with `cargo bench -p shared`. The memory used per chunk is printed before the timings.
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::mem::size_of;
+
+use bevy::math::IVec3;
+use criterion::{criterion_group, criterion_main, Criterion};
+use shared::world::{
+    BlockData, BlockDirection, BlockId, PalettedBlocks, ServerChunk, ServerChunkWorldMap, WorldMap,
+};
+use shared::CHUNK_SIZE;
+
+/// Chunk storage before the palette
+type HashMapChunk = HashMap<IVec3, BlockData>;
+
+/// Side of the cube of chunks the lookups are done in
+const WORLD_SIZE: i32 = 4;
+
+fn block(id: BlockId) -> BlockData {
+    BlockData::new(id, BlockDirection::Front)
+}
+
+/// Blocks of a chunk cut through the ground : stone with a few ores, dirt, grass, then air
+fn terrain_blocks() -> Vec<(IVec3, BlockData)> {
+    let mut blocks = Vec::new();
+    for x in 0..CHUNK_SIZE {
+        for z in 0..CHUNK_SIZE {
+            let height = 8 + (x + z) % 4;
+            for y in 0..height {
+                let id = if y == height - 1 {
+                    BlockId::Grass
+                } else if y >= height - 3 {
+                    BlockId::Dirt
+                } else if (x * 7 + y * 13 + z * 5) % 29 == 0 {
+                    BlockId::IronOre
+                } else {
+                    BlockId::Stone
+                };
+                blocks.push((IVec3::new(x, y, z), block(id)));
+            }
+        }
+    }
+    blocks
+}
+
+/// Every position holds a block, about half of them different from each other
+fn noisy_blocks() -> Vec<(IVec3, BlockData)> {
+    let mut blocks = Vec::new();
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let i = (x * CHUNK_SIZE + y) * CHUNK_SIZE + z;
+                blocks.push((
+                    IVec3::new(x, y, z),
+                    BlockData {
+                        id: BlockId::Stone,
+                        direction: BlockDirection::Front,
+                        breaking_progress: (i % 256) as u8,
+                    },
+                ));
+            }
+        }
+    }
+    blocks
+}
+
+fn hashmap_chunk_size(chunk: &HashMapChunk) -> usize {
+    // One control byte per bucket on top of the entries
+    chunk.capacity() * (size_of::<(IVec3, BlockData)>() + 1)
+}
+
+fn print_memory_per_chunk() {
+    println!("Memory per chunk (heap, bytes) :");
+    for (name, blocks) in [
+        ("empty", Vec::new()),
+        ("terrain", terrain_blocks()),
+        ("noisy", noisy_blocks()),
+    ] {
+        let hashmap: HashMapChunk = blocks.iter().copied().collect();
+        let paletted: PalettedBlocks = blocks.iter().copied().collect();
+        println!(
+            "  {name:<8} HashMap {:>7}  PalettedBlocks {:>7}",
+            hashmap_chunk_size(&hashmap),
+            paletted.heap_size()
+        );
+    }
+}
+
+/// Same lookup as `get_block_by_coordinates`, on chunks stored as a `HashMap`
+fn get_block_from_hashmap_chunks<'a>(
+    chunks: &'a HashMap<IVec3, HashMapChunk>,
+    position: &IVec3,
+) -> Option<&'a BlockData> {
+    let chunk_pos = position.div_euclid(IVec3::splat(CHUNK_SIZE));
+    chunks
+        .get(&chunk_pos)?
+        .get(&position.rem_euclid(IVec3::splat(CHUNK_SIZE)))
+}
+
+fn world_positions() -> Vec<IVec3> {
+    let size = WORLD_SIZE * CHUNK_SIZE;
+    (0..size * size * size)
+        .step_by(7)
+        .map(|i| IVec3::new(i / (size * size), (i / size) % size, i % size))
+        .collect()
+}
+
+fn lookups(c: &mut Criterion) {
+    print_memory_per_chunk();
+
+    let blocks = terrain_blocks();
+    let mut hashmap_chunks = HashMap::new();
+    let mut paletted_chunks = ServerChunkWorldMap::default();
+    for x in 0..WORLD_SIZE {
+        for y in 0..WORLD_SIZE {
+            for z in 0..WORLD_SIZE {
+                let chunk_pos = IVec3::new(x, y, z);
+                hashmap_chunks.insert(chunk_pos, blocks.iter().copied().collect::<HashMapChunk>());
+                paletted_chunks.map.insert(
+                    chunk_pos,
+                    ServerChunk {
+                        blocks: blocks.iter().copied().collect(),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+
+    let positions = world_positions();
+    let mut group = c.benchmark_group("get_block_by_coordinates");
+    group.bench_function("HashMap", |b| {
+        b.iter(|| {
+            positions
+                .iter()
+                .filter(|pos| get_block_from_hashmap_chunks(&hashmap_chunks, pos).is_some())
+                .count()
+        })
+    });
+    group.bench_function("PalettedBlocks", |b| {
+        b.iter(|| {
+            positions
+                .iter()
+                .filter(|pos| paletted_chunks.get_block_by_coordinates(pos).is_some())
+                .count()
+        })
+    });
+    group.finish();
+
+    c.bench_function("PalettedBlocks::insert terrain chunk", |b| {
+        b.iter(|| black_box(blocks.iter().copied().collect::<PalettedBlocks>()))
+    });
+}
+
+criterion_group!(benches, lookups);
+criterion_main!(benches);
@@ -1,7 +1,24 @@
 use bevy::prelude::*;
 
 pub const PROTOCOL_ID: u64 = 0;
+/// Version of the messages exchanged between clients and servers,
+/// to be increased whenever one of them changes
+pub const PROTOCOL_VERSION: u32 = 3;
 pub const TICKS_PER_SECOND: u64 = 20;
+pub const DEFAULT_AUTOSAVE_INTERVAL_SECS: u64 = 5 * 60;
+pub const DEFAULT_BACKUP_COUNT: u32 = 5;
+/// Interval between two backups of the world, in seconds
+pub const DEFAULT_BACKUP_INTERVAL_SECS: u64 = 6 * 60 * 60;
+pub const DEFAULT_BACKUP_MAX_AGE_SECS: u64 = 7 * 24 * 60 * 60;
+/// Largest view distance granted to clients, in chunks
+pub const DEFAULT_MAX_VIEW_DISTANCE: u32 = 8;
+pub const DEFAULT_MAX_PLAYERS: u32 = 16;
+/// Interval between two heartbeats of a client, in seconds
+pub const HEARTBEAT_INTERVAL_SECS: u64 = 1;
+/// Clients not heard from for this long are kicked, in seconds
+pub const DEFAULT_HEARTBEAT_TIMEOUT_SECS: u64 = 30;
+/// Clients which do not authenticate for this long after connecting are kicked, in seconds
+pub const DEFAULT_AUTH_TIMEOUT_SECS: u64 = 10;
 pub const CHUNK_SIZE: i32 = 16;
 pub const MAX_INVENTORY_SLOTS: u32 = 4 * 9;
 pub const HALF_BLOCK: Vec3 = Vec3 {
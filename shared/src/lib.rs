@@ -1,4 +1,4 @@
-use std::{path::PathBuf, time::Duration};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
 use bevy_ecs::resource::Resource;
 use bevy_log::debug;
@@ -12,8 +12,10 @@ pub mod utils;
 pub mod world;
 
 pub use constants::*;
-use messages::{ClientToServerMessage, ServerToClientMessage};
+use messages::{ClientToServerMessage, PlayerId, ServerToClientMessage};
+use serde::{Deserialize, Serialize};
 use utils::format_bytes;
+use world::{PregenerationArea, WorldGeneratorConfig};
 
 #[derive(Resource, Debug, Clone)]
 pub struct GameFolderPaths {
@@ -30,6 +32,45 @@ pub struct SpecialFlag {
 pub struct GameServerConfig {
     pub world_name: String,
     pub is_solo: bool,
+    /// Interval between two automatic saves, in seconds (0 disables autosaves)
+    pub autosave_interval_secs: u64,
+    /// Interval between two backups of the world, in seconds (0 disables backups)
+    pub backup_interval_secs: u64,
+    /// Maximum number of world backups to keep
+    pub backup_count: u32,
+    /// Backups older than this are deleted, in seconds (0 keeps them regardless of age)
+    pub backup_max_age_secs: u64,
+    /// Generator used if the world does not exist yet, existing worlds keep their own
+    pub world_generator: WorldGeneratorConfig,
+    /// Area to pre-generate once the server is started
+    pub pregenerate: Option<PregenerationArea>,
+    /// Clients asking for a larger view distance are capped to this one, in chunks
+    pub max_view_distance: u32,
+    /// Only accepts clients holding a connect token issued with the private key of the server
+    pub secure: bool,
+    /// Address clients reach the server at, which connect tokens are issued for.
+    /// Defaults to the address the server listens on
+    pub public_address: Option<SocketAddr>,
+    /// Clients joining once this many players are connected are rejected
+    pub max_players: u32,
+    /// Only accepts the players listed in the whitelist of the server
+    pub whitelist: bool,
+    /// Clients not heard from for this long are kicked, in seconds
+    pub heartbeat_timeout_secs: u64,
+    /// Clients which do not authenticate for this long after connecting are kicked, in seconds
+    pub auth_timeout_secs: u64,
+}
+
+/// Connect token issued to a player, along with the account it was issued for.\
+/// The account is also written in the encrypted part of the token, which only the server can read
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+pub struct AccountToken {
+    pub account_id: PlayerId,
+    pub username: String,
+    /// Server the token was issued for, the client refuses to connect anywhere else with it
+    pub server_address: SocketAddr,
+    /// Netcode connect token, as written by `ConnectToken::write`
+    pub connect_token: Vec<u8>,
 }
 
 const MAX_MEMORY: usize = 128 * 1024 * 1024;
@@ -167,7 +208,10 @@ impl ChannelResolvableExt for ClientToServerMessage {
 impl ChannelResolvableExt for ServerToClientMessage {
     fn get_channel_id(&self) -> u8 {
         match self {
-            ServerToClientMessage::WorldUpdate(_) => STC_CHUNK_DATA_CHANNEL,
+            // Same channel as the chunks, so that updates never arrive before the chunk they apply to
+            ServerToClientMessage::WorldUpdate(_)
+            | ServerToClientMessage::BlockUpdate(_)
+            | ServerToClientMessage::ChunkUnload(_) => STC_CHUNK_DATA_CHANNEL,
             ServerToClientMessage::AuthRegisterResponse(_) => STC_AUTH_CHANNEL,
             _ => STC_STANDARD_CHANNEL,
         }
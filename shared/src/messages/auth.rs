@@ -1,9 +1,47 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 use super::{ClientToServerMessage, PlayerSpawnEvent, ServerToClientMessage};
+use crate::utils::{enum_variant_names, stable_hash};
+use crate::world::{BiomeDefinition, BlockId, ItemId};
+use crate::PROTOCOL_VERSION;
 
+/// Versions a client and a server must share to play together
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct GameVersion {
+    pub protocol: u32,
+    /// Hash of the `BlockId` table, blocks being sent by index
+    pub blocks_hash: u64,
+    /// Hash of the `ItemId` table, items being sent by index
+    pub items_hash: u64,
+}
+
+impl GameVersion {
+    /// Version of this build
+    pub fn current() -> Self {
+        Self {
+            protocol: PROTOCOL_VERSION,
+            blocks_hash: stable_hash(enum_variant_names::<BlockId>().join(",").as_bytes()),
+            items_hash: stable_hash(enum_variant_names::<ItemId>().join(",").as_bytes()),
+        }
+    }
+}
+
+impl fmt::Display for GameVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "protocol {} (blocks {:016x}, items {:016x})",
+            self.protocol, self.blocks_hash, self.items_hash
+        )
+    }
+}
+
+/// First message of every client.\
+/// Its layout must never change, so that a server can tell any client why it is rejected
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct AuthRegisterRequest {
+    pub version: GameVersion,
     pub username: String,
 }
 
@@ -20,10 +58,67 @@ pub struct AuthRegisterResponse {
     pub tick: u64,
     pub timestamp_ms: u64,
     pub players: Vec<PlayerSpawnEvent>, // all players (including the new one)
+    /// Biomes of the server, including its custom ones
+    pub biomes: Vec<BiomeDefinition>,
 }
 
 impl From<AuthRegisterResponse> for ServerToClientMessage {
     fn from(val: AuthRegisterResponse) -> Self {
-        ServerToClientMessage::AuthRegisterResponse(val)
+        ServerToClientMessage::AuthRegisterResponse(Ok(val))
+    }
+}
+
+/// Reason why a server refused a client.\
+/// Reasons are only ever added at the end, so that clients of any version can read them
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum AuthRegisterError {
+    VersionMismatch {
+        server: GameVersion,
+        client: GameVersion,
+    },
+    /// A connected player already uses this name
+    NameTaken,
+    ServerFull {
+        max_players: u32,
+    },
+    Banned,
+    NotWhitelisted,
+}
+
+impl fmt::Display for AuthRegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthRegisterError::VersionMismatch { server, client }
+                if server.protocol != client.protocol =>
+            {
+                write!(
+                    f,
+                    "Incompatible versions : the server uses protocol {}, this game uses protocol {}",
+                    server.protocol, client.protocol
+                )
+            }
+            AuthRegisterError::VersionMismatch { .. } => {
+                write!(
+                    f,
+                    "Incompatible versions : the server does not have the same blocks and items as this game"
+                )
+            }
+            AuthRegisterError::NameTaken => {
+                write!(f, "A player with the same name is already connected")
+            }
+            AuthRegisterError::ServerFull { max_players } => {
+                write!(f, "The server is full ({} players)", max_players)
+            }
+            AuthRegisterError::Banned => write!(f, "You are banned from this server"),
+            AuthRegisterError::NotWhitelisted => {
+                write!(f, "You are not on the whitelist of this server")
+            }
+        }
+    }
+}
+
+impl From<AuthRegisterError> for ServerToClientMessage {
+    fn from(val: AuthRegisterError) -> Self {
+        ServerToClientMessage::AuthRegisterResponse(Err(val))
     }
 }
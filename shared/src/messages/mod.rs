@@ -15,19 +15,30 @@ pub type PlayerId = u64;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ClientToServerMessage {
+    /// Must stay the first variant, see `AuthRegisterRequest`
     AuthRegisterRequest(AuthRegisterRequest),
     ChatMessage(ChatMessageRequest),
     Exit,
     PlayerInputs(Vec<PlayerFrameInput>),
     SaveWorldRequest,
+    /// View distance wanted by the client, in chunks
+    SetViewDistance(u32),
+    /// Sent periodically, so that the server can tell frozen clients from idle ones
+    Heartbeat,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ServerToClientMessage {
-    AuthRegisterResponse(AuthRegisterResponse),
+    /// Must stay the first variant, so that clients of any version can read why they are rejected
+    AuthRegisterResponse(Result<AuthRegisterResponse, AuthRegisterError>),
     ChatConversation(ChatConversation),
     WorldUpdate(WorldUpdate),
+    BlockUpdate(BlockUpdate),
+    ChunkUnload(ChunkUnload),
+    /// View distance granted to the client, which may be lower than the one it asked for
+    ViewDistance(u32),
     PlayerSpawn(PlayerSpawnEvent),
+    PlayerDespawn(PlayerDespawnEvent),
     MobUpdate(MobUpdateEvent),
     PlayerUpdate(PlayerUpdateEvent),
 }
@@ -18,11 +18,19 @@ pub enum NetworkAction {
     RightClick,
 }
 
+/// Persistent state of a player, written to its save file and sent when it spawns.\
+/// Fields added later must have a default, so that older saves can still be read
 #[derive(Serialize, Deserialize, Default, PartialEq, Debug, Clone)]
 pub struct PlayerSave {
     pub position: Vec3,
     pub camera_transform: Transform,
     pub is_flying: bool,
+    #[serde(default)]
+    pub inventory: Inventory,
+    #[serde(default)]
+    pub hotbar_slot: u32,
+    #[serde(default)]
+    pub view_mode: ViewMode,
 }
 
 #[derive(Event, Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -32,6 +40,12 @@ pub struct PlayerSpawnEvent {
     pub data: PlayerSave,
 }
 
+/// A player left the server, and must be removed from the world of the other clients
+#[derive(Event, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct PlayerDespawnEvent {
+    pub id: PlayerId,
+}
+
 #[derive(Event, Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct PlayerUpdateEvent {
     pub id: PlayerId,
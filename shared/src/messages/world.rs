@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::world::{ItemStack, MobId, ServerChunk, ServerMob};
+use crate::world::{BlockData, ItemStack, MobId, ServerChunk, ServerMob};
 use bevy::{
     math::{IVec3, Vec3},
     prelude::Event,
@@ -18,6 +18,22 @@ pub struct WorldUpdate {
     pub item_stacks: Vec<ItemStackUpdateEvent>,
 }
 
+/// Blocks changed during a tick, in chunks the client already received.\
+/// Whole chunks are only sent by `WorldUpdate` when a client loads them
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct BlockUpdate {
+    pub tick: u64,
+    /// Global position of each changed block, with its new state or `None` if it was removed
+    pub blocks: Vec<(IVec3, Option<BlockData>)>,
+}
+
+/// Chunks which went out of the view distance of the client, which should free them.\
+/// They are sent again by `WorldUpdate` if they come back in range
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct ChunkUnload {
+    pub chunks: Vec<IVec3>,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize, Clone, Event)]
 pub struct ItemStackUpdateEvent {
     pub id: u128,
@@ -6,7 +6,7 @@ use bevy_platform::collections::HashMap;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    messages::PlayerId,
+    messages::{PlayerId, PlayerSave},
     world::{ItemId, ItemStack, ItemType},
     MAX_INVENTORY_SLOTS,
 };
@@ -131,6 +131,8 @@ pub struct Player {
     pub on_ground: bool,
     pub is_flying: bool,
     pub inventory: Inventory,
+    pub hotbar_slot: u32,
+    pub view_mode: ViewMode,
     pub height: f32,
     pub width: f32,
     pub last_input_processed: u64,
@@ -147,12 +149,39 @@ impl Player {
             on_ground: true,
             is_flying: false,
             inventory: Inventory::new(),
+            hotbar_slot: 0,
+            view_mode: ViewMode::default(),
             height: 1.8,
             width: 0.8,
             last_input_processed: 0,
         }
     }
 
+    /// Restores a player from its persistent state
+    pub fn from_save(id: PlayerId, name: String, save: PlayerSave) -> Self {
+        Self {
+            position: save.position,
+            camera_transform: save.camera_transform,
+            is_flying: save.is_flying,
+            inventory: save.inventory,
+            hotbar_slot: save.hotbar_slot,
+            view_mode: save.view_mode,
+            ..Self::new(id, name, save.position, save.camera_transform)
+        }
+    }
+
+    /// Persistent state of the player, see `PlayerSave`
+    pub fn to_save(&self) -> PlayerSave {
+        PlayerSave {
+            position: self.position,
+            camera_transform: self.camera_transform,
+            is_flying: self.is_flying,
+            inventory: self.inventory.clone(),
+            hotbar_slot: self.hotbar_slot,
+            view_mode: self.view_mode,
+        }
+    }
+
     pub fn toggle_fly_mode(&mut self) {
         self.is_flying = !self.is_flying;
         self.velocity = Vec3::ZERO;
@@ -170,6 +199,8 @@ impl Default for Player {
             on_ground: true,
             is_flying: false,
             inventory: Inventory::new(),
+            hotbar_slot: 0,
+            view_mode: ViewMode::default(),
             height: 1.8,
             width: 0.8,
             last_input_processed: 0,
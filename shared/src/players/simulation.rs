@@ -26,6 +26,9 @@ pub fn simulate_player_actions(
     // debug!("Player position before = {:?}", player.position);
     // debug!("Player view mode = {:?}", action.view_mode);
 
+    player.hotbar_slot = action.hotbar_slot;
+    player.view_mode = action.view_mode;
+
     simulate_player_block_interactions(player, world_map, action, caller_type);
     simulate_player_movement(player, world_map, action);
 }
@@ -1,4 +1,7 @@
 use bevy::prelude::*;
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
 
 pub fn format_bytes(bytes: u64) -> String {
     const UNITS: [&str; 5] = ["bytes", "kB", "MB", "GB", "TB"];
@@ -19,3 +22,22 @@ pub fn format_bytes(bytes: u64) -> String {
         format!("{size:.1} {unit}")
     }
 }
+
+/// FNV-1a hash of some bytes, which unlike the std hashers never changes between builds
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Names of the variants of a fieldless enum, in the order they are encoded in messages.\
+/// Variants are decoded one index after the other, until an index matches no variant
+pub fn enum_variant_names<T: DeserializeOwned + Debug>() -> Vec<String> {
+    (0u32..)
+        .map_while(|index| {
+            let payload = bincode::options().serialize(&index).ok()?;
+            bincode::options().deserialize::<T>(&payload).ok()
+        })
+        .map(|variant| format!("{variant:?}"))
+        .collect()
+}
@@ -0,0 +1,230 @@
+use bevy_ecs::resource::Resource;
+use bevy_log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use super::{BiomeTint, BlockId};
+use crate::CHUNK_SIZE;
+
+/// Biomes shipped with the game, custom biome files are loaded on top of them
+const BUILTIN_BIOMES: [&str; 10] = [
+    include_str!("../../../data/biomes/plains.ron"),
+    include_str!("../../../data/biomes/forest.ron"),
+    include_str!("../../../data/biomes/medium_mountain.ron"),
+    include_str!("../../../data/biomes/high_mountain_grass.ron"),
+    include_str!("../../../data/biomes/desert.ron"),
+    include_str!("../../../data/biomes/ice_plain.ron"),
+    include_str!("../../../data/biomes/flower_plains.ron"),
+    include_str!("../../../data/biomes/shallow_ocean.ron"),
+    include_str!("../../../data/biomes/ocean.ron"),
+    include_str!("../../../data/biomes/deep_ocean.ron"),
+];
+
+/// Block placed on top of the surface, e.g. flowers
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DecorationRule {
+    pub block: BlockId,
+    /// Chance for each surface block to get this decoration
+    pub density: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TreeKind {
+    Tree { trunk: BlockId, leaves: BlockId },
+    BigTree { trunk: BlockId, leaves: BlockId },
+    Cactus { block: BlockId },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TreeRule {
+    pub kind: TreeKind,
+    /// Chance for each surface block to grow this tree
+    pub density: f32,
+}
+
+/// Every parameter of a biome, as written in the biome files
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BiomeDefinition {
+    /// Unique name of the biome, a custom biome with the name of a builtin one replaces it
+    pub name: String,
+    pub base_height: i32,
+    pub height_variation: i32,
+    pub surface_block: BlockId,
+    pub sub_surface_block: BlockId,
+    /// Climate where the biome appears, as `(min, max)` ranges, max excluded.
+    /// Both values go from 0 to 1
+    pub temperature: (f64, f64),
+    pub humidity: (f64, f64),
+    /// Whether the surface can be shaped into overhangs
+    #[serde(default)]
+    pub overhangs: bool,
+    /// At most one decoration is placed per surface block, the first one to succeed
+    #[serde(default)]
+    pub decorations: Vec<DecorationRule>,
+    #[serde(default)]
+    pub trees: Vec<TreeRule>,
+    /// Color distant terrain fades to, while the camera is in this biome
+    pub sky_color: [f32; 3],
+    /// Tints of the grass, of the leaves and of the water
+    pub grass_color: [f32; 3],
+    pub foliage_color: [f32; 3],
+    pub water_color: [f32; 3],
+}
+
+impl BiomeDefinition {
+    pub fn get_tint_color(&self, tint: BiomeTint) -> [f32; 3] {
+        match tint {
+            BiomeTint::Grass => self.grass_color,
+            BiomeTint::Foliage => self.foliage_color,
+            BiomeTint::Water => self.water_color,
+        }
+    }
+
+    fn climate_distance(&self, temperature: f64, humidity: f64) -> f64 {
+        let distance_to_range = |(min, max): (f64, f64), value: f64| {
+            if value < min {
+                min - value
+            } else if value >= max {
+                value - max
+            } else {
+                0.
+            }
+        };
+
+        distance_to_range(self.temperature, temperature)
+            + distance_to_range(self.humidity, humidity)
+    }
+}
+
+/// All the biomes which can be generated
+#[derive(Resource, Debug, Clone)]
+pub struct BiomeRegistry {
+    biomes: Vec<BiomeDefinition>,
+}
+
+impl BiomeRegistry {
+    /// Registry holding only the biomes shipped with the game
+    pub fn builtin() -> Self {
+        let biomes = BUILTIN_BIOMES
+            .iter()
+            .map(|contents| ron::de::from_str(contents).expect("invalid builtin biome file"))
+            .collect();
+
+        Self { biomes }
+    }
+
+    /// Builtin biomes, along with the custom biomes found in `custom_folder`.\
+    /// Invalid biome files are skipped
+    pub fn load(custom_folder: &Path) -> Self {
+        let mut registry = Self::builtin();
+
+        let Ok(dir) = fs::read_dir(custom_folder) else {
+            return registry;
+        };
+
+        let mut paths: Vec<_> = dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+            .collect();
+        // Sorted, so that the biomes are in the same order on every machine
+        paths.sort();
+
+        for path in paths {
+            let definition = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|contents| {
+                    ron::de::from_str::<BiomeDefinition>(&contents).map_err(|err| err.to_string())
+                });
+
+            match definition {
+                Ok(definition) => {
+                    info!(
+                        "Loaded custom biome {} from {}",
+                        definition.name,
+                        path.display()
+                    );
+                    registry.register(definition);
+                }
+                Err(err) => error!("Could not load biome file {} : {}", path.display(), err),
+            }
+        }
+
+        registry
+    }
+
+    /// Adds a biome, replacing the one with the same name if any
+    pub fn register(&mut self, definition: BiomeDefinition) {
+        match self.biomes.iter_mut().find(|b| b.name == definition.name) {
+            Some(existing) => *existing = definition,
+            None => self.biomes.push(definition),
+        }
+    }
+
+    /// Registry made of the given biomes only, e.g. those received from the server
+    pub fn from_definitions(biomes: Vec<BiomeDefinition>) -> Self {
+        Self { biomes }
+    }
+
+    pub fn definitions(&self) -> &[BiomeDefinition] {
+        &self.biomes
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BiomeDefinition> {
+        self.biomes.iter().find(|b| b.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BiomeDefinition> {
+        self.biomes.iter()
+    }
+
+    /// Biome of a given climate. If no biome covers it, the closest one is used
+    pub fn find(&self, temperature: f64, humidity: f64) -> &BiomeDefinition {
+        self.biomes
+            .iter()
+            .min_by(|a, b| {
+                a.climate_distance(temperature, humidity)
+                    .total_cmp(&b.climate_distance(temperature, humidity))
+            })
+            .expect("the biome registry is empty")
+    }
+}
+
+/// Name of the biome of every column of a chunk, stored as indices into a palette of names.\
+/// Empty for chunks generated before biomes were stored, their biome is unknown
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ColumnBiomes {
+    palette: Vec<String>,
+    columns: Vec<u8>,
+}
+
+impl ColumnBiomes {
+    fn index(x: i32, z: i32) -> usize {
+        (x * CHUNK_SIZE + z) as usize
+    }
+
+    /// Sets the biome of the column at local position `(x, z)`
+    pub fn set(&mut self, x: i32, z: i32, name: &str) {
+        let palette_index = match self.palette.iter().position(|n| n == name) {
+            Some(index) => index,
+            None => {
+                self.palette.push(name.to_string());
+                self.palette.len() - 1
+            }
+        };
+
+        if self.columns.is_empty() {
+            self.columns = vec![0; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+        }
+        self.columns[Self::index(x, z)] = palette_index as u8;
+    }
+
+    /// Name of the biome of the column at local position `(x, z)`, if known
+    pub fn get(&self, x: i32, z: i32) -> Option<&str> {
+        let palette_index = *self.columns.get(Self::index(x, z))?;
+        self.palette
+            .get(palette_index as usize)
+            .map(|name| name.as_str())
+    }
+}
@@ -42,6 +42,11 @@ pub enum BlockId {
     SpruceLeaves,
     SpruceLog,
     Water,
+    CoalOre,
+    IronOre,
+    GoldOre,
+    DiamondOre,
+    Gravel,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -93,6 +98,14 @@ pub enum BlockHitbox {
     None,
 }
 
+/// Colors of a biome which can be applied to blocks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BiomeTint {
+    Grass,
+    Foliage,
+    Water,
+}
+
 impl BlockId {
     pub fn get_hitbox(&self) -> BlockHitbox {
         match *self {
@@ -112,8 +125,14 @@ impl BlockId {
         }
     }
 
-    pub fn is_biome_colored() -> bool {
-        false
+    /// Biome color this block is tinted with, if any
+    pub fn get_biome_tint(&self) -> Option<BiomeTint> {
+        match *self {
+            Self::Grass | Self::TallGrass => Some(BiomeTint::Grass),
+            Self::OakLeaves | Self::SpruceLeaves => Some(BiomeTint::Foliage),
+            Self::Water => Some(BiomeTint::Water),
+            _ => None,
+        }
     }
 
     pub fn get_break_time(&self) -> u8 {
@@ -136,6 +155,12 @@ impl BlockId {
             Self::Snow => 9,
             Self::SpruceLeaves => 2,
             Self::SpruceLog => 10,
+            // Rarer and deeper ores take longer to mine
+            Self::CoalOre => 12,
+            Self::IronOre => 15,
+            Self::GoldOre => 18,
+            Self::DiamondOre => 24,
+            Self::Gravel => 6,
             _ => 100,
         }
     }
@@ -192,6 +217,11 @@ impl BlockId {
             BlockId::TallGrass => vec![(1, ItemId::TallGrass, 1)],
             BlockId::SpruceLog => vec![(1, ItemId::SpruceLog, 1)],
             BlockId::Snow => vec![(1, ItemId::Snowball, 4)],
+            BlockId::CoalOre => vec![(1, ItemId::Coal, 1)],
+            BlockId::IronOre => vec![(1, ItemId::IronOre, 1)],
+            BlockId::GoldOre => vec![(1, ItemId::GoldOre, 1)],
+            BlockId::DiamondOre => vec![(1, ItemId::Diamond, 1)],
+            BlockId::Gravel => vec![(1, ItemId::Gravel, 1)],
             BlockId::Water => vec![],
             _ => vec![],
         }
@@ -199,7 +229,11 @@ impl BlockId {
 
     pub fn get_tags(&self) -> Vec<BlockTags> {
         match *self {
-            BlockId::Stone => vec![BlockTags::Stone, BlockTags::Solid],
+            BlockId::Stone
+            | BlockId::CoalOre
+            | BlockId::IronOre
+            | BlockId::GoldOre
+            | BlockId::DiamondOre => vec![BlockTags::Stone, BlockTags::Solid],
             _ => vec![BlockTags::Solid],
         }
     }
@@ -225,3 +259,28 @@ impl BlockId {
 }
 
 impl GameElementId for BlockId {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ores_take_longer_to_break_than_stone() {
+        let ores = [
+            BlockId::CoalOre,
+            BlockId::IronOre,
+            BlockId::GoldOre,
+            BlockId::DiamondOre,
+        ];
+
+        assert!(BlockId::CoalOre.get_break_time() > BlockId::Stone.get_break_time());
+        for pair in ores.windows(2) {
+            assert!(
+                pair[0].get_break_time() < pair[1].get_break_time(),
+                "{:?} should break faster than {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+}
@@ -5,15 +5,15 @@ use crate::world::{
 };
 use crate::CHUNK_SIZE;
 
-use bevy::math::{bounding::Aabb3d, IVec3, Vec3};
+use bevy::math::{bounding::Aabb3d, IVec2, IVec3, Vec3};
 use bevy_ecs::resource::Resource;
 use bevy_log::info;
 use bevy_log::warn;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 
-use super::{BlockData, ItemId, ItemType, MobId, ServerMob};
+use super::{BlockData, ColumnBiomes, ItemId, ItemType, MobId, PalettedBlocks, ServerMob};
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct ServerItemStack {
@@ -26,10 +26,10 @@ pub struct ServerItemStack {
 
 #[derive(Clone, Default, Serialize, Deserialize, Debug)]
 pub struct ServerChunk {
-    pub map: HashMap<IVec3, BlockData>,
+    pub blocks: PalettedBlocks,
     /// Timestamp marking the last update this chunk has received
     pub ts: u64,
-    pub sent_to_clients: Vec<PlayerId>,
+    pub biomes: ColumnBiomes,
 }
 
 // #[derive(Resource)]
@@ -45,120 +45,154 @@ pub struct ServerWorldMap {
     pub time: u64,
 }
 
+/// Blocks waiting to be written to chunks, indexed by chunk position.
+/// Block positions are local to their chunk
+pub type PendingBlocks = HashMap<IVec3, Vec<(IVec3, BlockData)>>;
+
 #[derive(Default, Clone, Serialize, Deserialize, Debug)]
 pub struct ServerChunkWorldMap {
     pub map: HashMap<IVec3, ServerChunk>,
-    pub chunks_to_update: Vec<IVec3>,
+    /// Global positions of the blocks changed since the last broadcast
+    pub updated_blocks: Vec<IVec3>,
+    /// Parts of structures generated in neighbouring chunks, for chunks which are not loaded
+    pub pending_blocks: PendingBlocks,
+    /// Loaded chunks changed since they were last written to disk
+    #[serde(skip)]
+    pub changed_chunks: HashSet<IVec3>,
+}
+
+impl ServerChunkWorldMap {
+    /// Name of the biome at a global block position, if its chunk is loaded and its biome known
+    pub fn get_biome(&self, global_block_pos: &IVec3) -> Option<&str> {
+        let chunk = self.map.get(&global_block_to_chunk_pos(global_block_pos))?;
+        let local_pos = to_local_pos(global_block_pos);
+        chunk.biomes.get(local_pos.x, local_pos.z)
+    }
 }
 
 #[derive(Resource, Clone, Copy, Serialize, Deserialize, Default)]
 pub struct WorldSeed(pub u32);
 
-#[derive(Debug, Clone, Serialize, Deserialize, Copy, Default, PartialEq)]
-pub struct ItemStack {
-    pub item_id: ItemId,
-    pub item_type: ItemType,
-    pub nb: u32,
+/// Generator a world was created with. Persisted with the world, so that new chunks
+/// keep being generated the same way
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub enum WorldGeneratorConfig {
+    /// Biomes, caves and structures, from the world seed
+    #[default]
+    Noise,
+    /// Horizontal layers of blocks, from the bottom of the world up : `(block, thickness)`
+    Superflat { layers: Vec<(BlockId, u32)> },
+    /// No blocks at all, except a small platform to spawn on
+    Void,
+    /// Noise generation where every column belongs to the biome with this name
+    SingleBiome(String),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum BiomeType {
-    Plains,
-    Forest,
-    MediumMountain,
-    HighMountainGrass,
-    Desert,
-    IcePlain,
-    FlowerPlains,
-    ShallowOcean,
-    Ocean,
-    DeepOcean,
+impl WorldGeneratorConfig {
+    pub fn default_superflat() -> Self {
+        Self::Superflat {
+            layers: vec![
+                (BlockId::Bedrock, 1),
+                (BlockId::Dirt, 2),
+                (BlockId::Grass, 1),
+            ],
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct Biome {
-    pub biome_type: BiomeType,
-    pub base_height: i32,
-    pub height_variation: i32,
-    pub surface_block: BlockId,
-    pub sub_surface_block: BlockId,
+/// Largest radius, and half the largest side, of a pre-generation area, in blocks
+pub const MAX_PREGENERATION_RADIUS: i32 = 16384;
+
+/// Area of the world to generate ahead of time, in chunk columns
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum PregenerationArea {
+    /// Columns within `radius` columns of `center`
+    Radius { center: IVec2, radius: i32 },
+    /// Columns from `min` to `max`, inclusive
+    Rect { min: IVec2, max: IVec2 },
 }
 
-pub fn get_biome_data(biome_type: BiomeType) -> Biome {
-    match biome_type {
-        BiomeType::Plains => Biome {
-            biome_type: BiomeType::Plains,
-            base_height: 64,
-            height_variation: 1,
-            surface_block: BlockId::Grass,
-            sub_surface_block: BlockId::Dirt,
-        },
-        BiomeType::Forest => Biome {
-            biome_type: BiomeType::Forest,
-            base_height: 64,
-            height_variation: 2,
-            surface_block: BlockId::Grass,
-            sub_surface_block: BlockId::Dirt,
-        },
-        BiomeType::MediumMountain => Biome {
-            biome_type: BiomeType::MediumMountain,
-            base_height: 70,
-            height_variation: 4,
-            surface_block: BlockId::Grass,
-            sub_surface_block: BlockId::Dirt,
-        },
-        BiomeType::HighMountainGrass => Biome {
-            biome_type: BiomeType::HighMountainGrass,
-            base_height: 75,
-            height_variation: 7,
-            surface_block: BlockId::Grass,
-            sub_surface_block: BlockId::Dirt,
-        },
-        BiomeType::Desert => Biome {
-            biome_type: BiomeType::Desert,
-            base_height: 64,
-            height_variation: 1,
-            surface_block: BlockId::Sand,
-            sub_surface_block: BlockId::Sand,
-        },
-        BiomeType::IcePlain => Biome {
-            biome_type: BiomeType::IcePlain,
-            base_height: 64,
-            height_variation: 1,
-            surface_block: BlockId::Snow,
-            sub_surface_block: BlockId::Ice,
-        },
-        BiomeType::FlowerPlains => Biome {
-            biome_type: BiomeType::FlowerPlains,
-            base_height: 64,
-            height_variation: 1,
-            surface_block: BlockId::Grass,
-            sub_surface_block: BlockId::Dirt,
-        },
-        BiomeType::ShallowOcean => Biome {
-            biome_type: BiomeType::ShallowOcean,
-            base_height: 60,
-            height_variation: 1,
-            surface_block: BlockId::Sand,
-            sub_surface_block: BlockId::Sand,
-        },
-        BiomeType::Ocean => Biome {
-            biome_type: BiomeType::DeepOcean,
-            base_height: 55,
-            height_variation: 2,
-            surface_block: BlockId::Sand,
-            sub_surface_block: BlockId::Sand,
-        },
-        BiomeType::DeepOcean => Biome {
-            biome_type: BiomeType::DeepOcean,
-            base_height: 50,
-            height_variation: 3,
-            surface_block: BlockId::Sand,
-            sub_surface_block: BlockId::Sand,
-        },
+impl PregenerationArea {
+    /// Parses an area given in block coordinates, either `radius <x> <z> <radius>`
+    /// or `rect <x1> <z1> <x2> <z2>`
+    pub fn parse(kind: &str, values: &[&str]) -> Result<Self, String> {
+        let values = values
+            .iter()
+            .map(|value| {
+                value
+                    .trim()
+                    .parse::<i32>()
+                    .map_err(|_| format!("Invalid coordinate : {value}"))
+            })
+            .collect::<Result<Vec<i32>, String>>()?;
+        let column = |x: i32, z: i32| IVec2::new(block_to_chunk_coord(x), block_to_chunk_coord(z));
+
+        match (kind, values.as_slice()) {
+            ("radius", [_, _, radius]) if !(0..=MAX_PREGENERATION_RADIUS).contains(radius) => {
+                Err(format!(
+                    "Invalid radius : {radius}, expected between 0 and {MAX_PREGENERATION_RADIUS}"
+                ))
+            }
+            ("radius", [x, z, radius]) => Ok(Self::Radius {
+                center: column(*x, *z),
+                radius: (radius + CHUNK_SIZE - 1) / CHUNK_SIZE,
+            }),
+            ("rect", [x1, z1, x2, z2])
+                if x1.abs_diff(*x2) > 2 * MAX_PREGENERATION_RADIUS as u32
+                    || z1.abs_diff(*z2) > 2 * MAX_PREGENERATION_RADIUS as u32 =>
+            {
+                Err(format!(
+                    "Invalid area : sides are limited to {} blocks",
+                    2 * MAX_PREGENERATION_RADIUS
+                ))
+            }
+            ("rect", [x1, z1, x2, z2]) => {
+                let (a, b) = (column(*x1, *z1), column(*x2, *z2));
+                Ok(Self::Rect {
+                    min: a.min(b),
+                    max: a.max(b),
+                })
+            }
+            _ => Err(format!(
+                "Invalid area : expected `radius <x> <z> <radius>` or `rect <x1> <z1> <x2> <z2>`, got `{kind}`"
+            )),
+        }
+    }
+
+    /// Every column of the area, closest to its center first.\
+    /// The order never changes, so that an interrupted pre-generation can resume where it stopped
+    pub fn columns(&self) -> Vec<IVec2> {
+        let (min, max) = match *self {
+            Self::Radius { center, radius } => (center - radius, center + radius),
+            Self::Rect { min, max } => (min, max),
+        };
+        let center = (min + max) / 2;
+
+        let mut columns = Vec::new();
+        for x in min.x..=max.x {
+            for z in min.y..=max.y {
+                let column = IVec2::new(x, z);
+                if let Self::Radius { center, radius } = *self {
+                    if (column - center).length_squared() > radius * radius {
+                        continue;
+                    }
+                }
+                columns.push(column);
+            }
+        }
+
+        columns.sort_by_key(|column| ((*column - center).length_squared(), column.x, column.y));
+        columns
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Copy, Default, PartialEq)]
+pub struct ItemStack {
+    pub item_id: ItemId,
+    pub item_type: ItemType,
+    pub nb: u32,
+}
+
 pub trait WorldMap {
     fn get_block_mut_by_coordinates(&mut self, position: &IVec3) -> Option<&mut BlockData>;
     fn get_block_by_coordinates(&self, position: &IVec3) -> Option<&BlockData>;
@@ -232,13 +266,15 @@ impl WorldMap for ServerChunkWorldMap {
         let cx: i32 = block_to_chunk_coord(x);
         let cy: i32 = block_to_chunk_coord(y);
         let cz: i32 = block_to_chunk_coord(z);
-        let chunk = self.map.get_mut(&IVec3::new(cx, cy, cz));
+        let chunk_pos = IVec3::new(cx, cy, cz);
+        let chunk = self.map.get_mut(&chunk_pos);
         match chunk {
             Some(chunk) => {
+                self.changed_chunks.insert(chunk_pos);
                 let sub_x: i32 = ((x % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
                 let sub_y: i32 = ((y % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
                 let sub_z: i32 = ((z % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
-                chunk.map.get_mut(&IVec3::new(sub_x, sub_y, sub_z))
+                chunk.blocks.get_mut(&IVec3::new(sub_x, sub_y, sub_z))
             }
             None => {
                 warn!("Chunk not found for block at {:?} (mut)", position);
@@ -260,7 +296,7 @@ impl WorldMap for ServerChunkWorldMap {
                 let sub_x: i32 = ((x % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
                 let sub_y: i32 = ((y % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
                 let sub_z: i32 = ((z % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
-                chunk.map.get(&IVec3::new(sub_x, sub_y, sub_z))
+                chunk.blocks.get(&IVec3::new(sub_x, sub_y, sub_z))
             }
             None => {
                 warn!("Chunk not found for block at {:?}", position);
@@ -275,9 +311,6 @@ impl WorldMap for ServerChunkWorldMap {
         let kind: BlockData = *block;
 
         let chunk_pos: IVec3 = global_block_to_chunk_pos(global_block_pos);
-        let cx = chunk_pos.x;
-        let cy = chunk_pos.y;
-        let cz = chunk_pos.z;
 
         let chunk_map: &mut ServerChunk =
             self.map
@@ -285,8 +318,9 @@ impl WorldMap for ServerChunkWorldMap {
 
         let local_block_pos: IVec3 = to_local_pos(global_block_pos);
 
-        chunk_map.map.remove(&local_block_pos);
-        self.chunks_to_update.push(IVec3::new(cx, cy, cz));
+        chunk_map.blocks.remove(&local_block_pos);
+        self.changed_chunks.insert(chunk_pos);
+        self.updated_blocks.push(*global_block_pos);
 
         Some(kind)
     }
@@ -298,23 +332,21 @@ impl WorldMap for ServerChunkWorldMap {
         let cx: i32 = block_to_chunk_coord(x);
         let cy: i32 = block_to_chunk_coord(y);
         let cz: i32 = block_to_chunk_coord(z);
-        let chunk: &mut ServerChunk = self.map.entry(IVec3::new(cx, cy, cz)).or_default();
+        let chunk_pos = IVec3::new(cx, cy, cz);
+        let chunk: &mut ServerChunk = self.map.entry(chunk_pos).or_default();
         let sub_x: i32 = ((x % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
         let sub_y: i32 = ((y % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
         let sub_z: i32 = ((z % CHUNK_SIZE) + CHUNK_SIZE) % CHUNK_SIZE;
 
-        chunk.map.insert(IVec3::new(sub_x, sub_y, sub_z), block);
-        self.chunks_to_update.push(IVec3::new(cx, cy, cz));
+        chunk.blocks.insert(IVec3::new(sub_x, sub_y, sub_z), block);
+        self.changed_chunks.insert(chunk_pos);
+        self.updated_blocks.push(*position);
     }
 
     fn mark_block_for_update(&mut self, position: &IVec3) {
-        let x: i32 = position.x;
-        let y: i32 = position.y;
-        let z: i32 = position.z;
-        let cx: i32 = block_to_chunk_coord(x);
-        let cy: i32 = block_to_chunk_coord(y);
-        let cz: i32 = block_to_chunk_coord(z);
-        self.chunks_to_update.push(IVec3::new(cx, cy, cz));
+        self.changed_chunks
+            .insert(global_block_to_chunk_pos(position));
+        self.updated_blocks.push(*position);
     }
 }
 
@@ -322,3 +354,85 @@ impl WorldMap for ServerChunkWorldMap {
 /// types of elements in the game. Example : ItemId, BlockId...
 /// Used in texture atlases and such
 pub trait GameElementId: std::hash::Hash + Eq + PartialEq + Copy + Clone + Default + Debug {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pregeneration_areas() {
+        assert_eq!(
+            PregenerationArea::parse("radius", &["0", "-1", "100"]),
+            Ok(PregenerationArea::Radius {
+                center: IVec2::new(0, -1),
+                radius: 7,
+            })
+        );
+        assert_eq!(
+            PregenerationArea::parse("rect", &["40", "-20", "-17", " 5 "]),
+            Ok(PregenerationArea::Rect {
+                min: IVec2::new(-2, -2),
+                max: IVec2::new(2, 0),
+            })
+        );
+
+        for (kind, values) in [
+            ("radius", &["0", "0", "-1"][..]),
+            ("radius", &["0", "0"][..]),
+            ("radius", &["0", "0", "x"][..]),
+            ("square", &["0", "0", "10"][..]),
+            ("rect", &["0", "0", "10"][..]),
+        ] {
+            assert!(PregenerationArea::parse(kind, values).is_err());
+        }
+    }
+
+    #[test]
+    fn huge_pregeneration_areas_are_rejected() {
+        let max = MAX_PREGENERATION_RADIUS.to_string();
+        assert!(PregenerationArea::parse("radius", &["0", "0", &max]).is_ok());
+
+        let too_big = (MAX_PREGENERATION_RADIUS + 1).to_string();
+        assert!(PregenerationArea::parse("radius", &["0", "0", &too_big]).is_err());
+        assert!(PregenerationArea::parse("radius", &["0", "0", &i32::MAX.to_string()]).is_err());
+
+        let (min, max) = (i32::MIN.to_string(), i32::MAX.to_string());
+        assert!(PregenerationArea::parse("rect", &[&min, "0", &max, "0"]).is_err());
+        assert!(PregenerationArea::parse("rect", &["0", &min, "0", &max]).is_err());
+    }
+
+    #[test]
+    fn pregeneration_columns_are_sorted_closest_first() {
+        let area = PregenerationArea::Radius {
+            center: IVec2::new(3, -2),
+            radius: 4,
+        };
+        let columns = area.columns();
+
+        assert_eq!(columns[0], IVec2::new(3, -2));
+        assert!(columns
+            .iter()
+            .all(|column| (*column - IVec2::new(3, -2)).length_squared() <= 16));
+        assert!(columns
+            .windows(2)
+            .all(|pair| (pair[0] - IVec2::new(3, -2)).length_squared()
+                <= (pair[1] - IVec2::new(3, -2)).length_squared()));
+
+        let unique: std::collections::HashSet<IVec2> = columns.iter().copied().collect();
+        assert_eq!(unique.len(), columns.len());
+    }
+
+    #[test]
+    fn pregeneration_columns_order_is_stable() {
+        let area = PregenerationArea::Rect {
+            min: IVec2::new(-3, -1),
+            max: IVec2::new(4, 5),
+        };
+        let columns = area.columns();
+
+        assert_eq!(columns.len(), 8 * 7);
+        assert_eq!(area.columns(), columns);
+        // Ties are broken by coordinates, not by the iteration order
+        assert_eq!(&columns[..2], &[IVec2::new(0, 2), IVec2::new(-1, 2)]);
+    }
+}
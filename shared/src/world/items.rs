@@ -37,6 +37,11 @@ pub enum ItemId {
     Snow,
     Snowball,
     SpruceLog,
+    Coal,
+    IronOre,
+    GoldOre,
+    Diamond,
+    Gravel,
 }
 
 impl ItemId {
@@ -63,8 +68,11 @@ impl ItemId {
             Self::Cobblestone => ItemType::Block(BlockId::Cobblestone),
             Self::Snow => ItemType::Block(BlockId::Snow),
             Self::SpruceLog => ItemType::Block(BlockId::SpruceLog),
+            Self::IronOre => ItemType::Block(BlockId::IronOre),
+            Self::GoldOre => ItemType::Block(BlockId::GoldOre),
+            Self::Gravel => ItemType::Block(BlockId::Gravel),
 
-            Self::Snowball => ItemType::Generic,
+            Self::Snowball | Self::Coal | Self::Diamond => ItemType::Generic,
         }
     }
 }
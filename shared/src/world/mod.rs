@@ -1,13 +1,17 @@
+pub mod biomes;
 pub mod blocks;
 pub mod data;
 pub mod items;
 pub mod mobs;
+pub mod palette;
 pub mod raycast;
 mod utils;
 
+pub use biomes::*;
 pub use blocks::*;
 pub use data::*;
 pub use items::*;
 pub use mobs::*;
+pub use palette::*;
 pub use raycast::*;
 pub use utils::*;
@@ -0,0 +1,512 @@
+use bevy::math::IVec3;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::CHUNK_SIZE;
+
+use super::BlockData;
+
+const BLOCKS_PER_CHUNK: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Maximum number of bits needed to index the palette : every block of the chunk is different
+const MAX_BITS_PER_BLOCK: u8 = BLOCKS_PER_CHUNK.ilog2() as u8;
+
+/// Dense storage of the blocks of a chunk.
+///
+/// Each distinct block is stored once in a palette, and every position of the chunk
+/// holds a bit-packed index into this palette. Indices never span two words.\
+/// A chunk made of a single kind of block (e.g. empty) needs 0 bits per block.
+///
+/// Positions are local to the chunk, positions outside of it are ignored.
+#[derive(Clone, Debug)]
+pub struct PalettedBlocks {
+    /// Distinct blocks of the chunk, `None` is air
+    palette: Vec<Option<BlockData>>,
+    /// Number of positions using each palette entry, entries used 0 times can be reused
+    counts: Vec<u16>,
+    bits_per_block: u8,
+    data: Vec<u64>,
+}
+
+impl Default for PalettedBlocks {
+    fn default() -> Self {
+        Self {
+            palette: vec![None],
+            counts: vec![BLOCKS_PER_CHUNK as u16],
+            bits_per_block: 0,
+            data: Vec::new(),
+        }
+    }
+}
+
+fn local_index(pos: &IVec3) -> Option<usize> {
+    if pos.cmplt(IVec3::ZERO).any() || pos.cmpge(IVec3::splat(CHUNK_SIZE)).any() {
+        return None;
+    }
+    Some(((pos.x * CHUNK_SIZE + pos.y) * CHUNK_SIZE + pos.z) as usize)
+}
+
+fn index_to_local_pos(index: usize) -> IVec3 {
+    let index = index as i32;
+    IVec3::new(
+        index / (CHUNK_SIZE * CHUNK_SIZE),
+        (index / CHUNK_SIZE) % CHUNK_SIZE,
+        index % CHUNK_SIZE,
+    )
+}
+
+fn bits_needed(palette_len: usize) -> u8 {
+    if palette_len <= 1 {
+        0
+    } else {
+        (usize::BITS - (palette_len - 1).leading_zeros()) as u8
+    }
+}
+
+fn packed_len(bits_per_block: u8) -> usize {
+    if bits_per_block == 0 {
+        0
+    } else {
+        BLOCKS_PER_CHUNK.div_ceil(64 / bits_per_block as usize)
+    }
+}
+
+impl PalettedBlocks {
+    fn palette_index(&self, index: usize) -> usize {
+        if self.bits_per_block == 0 {
+            return 0;
+        }
+        let bits = self.bits_per_block as usize;
+        let per_word = 64 / bits;
+        let word = self.data[index / per_word];
+        ((word >> ((index % per_word) * bits)) & ((1 << bits) - 1)) as usize
+    }
+
+    fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+        if self.bits_per_block == 0 {
+            return;
+        }
+        let bits = self.bits_per_block as usize;
+        let per_word = 64 / bits;
+        let shift = (index % per_word) * bits;
+        let mask = ((1u64 << bits) - 1) << shift;
+        let word = &mut self.data[index / per_word];
+        *word = (*word & !mask) | ((palette_index as u64) << shift);
+    }
+
+    /// Rewrites every index with a new bit width
+    fn repack(&mut self, bits_per_block: u8, remap: impl Fn(usize) -> usize) {
+        let indices: Vec<usize> = (0..BLOCKS_PER_CHUNK)
+            .map(|i| remap(self.palette_index(i)))
+            .collect();
+
+        self.bits_per_block = bits_per_block;
+        self.data = vec![0; packed_len(bits_per_block)];
+        for (i, palette_index) in indices.into_iter().enumerate() {
+            self.set_palette_index(i, palette_index);
+        }
+    }
+
+    /// Drops unused and duplicate palette entries, shrinking indices if possible
+    fn compact(&mut self) {
+        let mut palette: Vec<Option<BlockData>> = Vec::new();
+        let mut counts: Vec<u16> = Vec::new();
+        let mut remap = vec![0; self.palette.len()];
+
+        for (old_index, block) in self.palette.iter().enumerate() {
+            if self.counts[old_index] == 0 {
+                continue;
+            }
+            let new_index = match palette.iter().position(|b| b == block) {
+                Some(new_index) => new_index,
+                None => {
+                    palette.push(*block);
+                    counts.push(0);
+                    palette.len() - 1
+                }
+            };
+            counts[new_index] += self.counts[old_index];
+            remap[old_index] = new_index;
+        }
+
+        let bits_per_block = bits_needed(palette.len());
+        self.repack(bits_per_block, |i| remap[i]);
+        self.palette = palette;
+        self.counts = counts;
+    }
+
+    /// Returns a palette entry holding `block`, adding one if needed
+    fn find_or_add(&mut self, block: Option<BlockData>) -> usize {
+        if let Some(index) = self.palette.iter().position(|b| *b == block) {
+            return index;
+        }
+        self.add_entry(block)
+    }
+
+    /// Adds a palette entry, even if an identical one already exists
+    fn add_entry(&mut self, block: Option<BlockData>) -> usize {
+        if let Some(index) = self.counts.iter().position(|count| *count == 0) {
+            self.palette[index] = block;
+            return index;
+        }
+
+        if bits_needed(self.palette.len() + 1) > self.bits_per_block {
+            self.compact();
+            let bits_per_block = bits_needed(self.palette.len() + 1).min(MAX_BITS_PER_BLOCK);
+            if bits_per_block > self.bits_per_block {
+                self.repack(bits_per_block, |i| i);
+            }
+        }
+
+        self.palette.push(block);
+        self.counts.push(0);
+        self.palette.len() - 1
+    }
+
+    fn replace(&mut self, index: usize, block: Option<BlockData>) -> Option<BlockData> {
+        let old_palette_index = self.palette_index(index);
+        let old_block = self.palette[old_palette_index];
+        if old_block == block {
+            return old_block;
+        }
+
+        // Release the old entry first, so that it can be reused if this was its last block
+        self.counts[old_palette_index] -= 1;
+        let new_palette_index = self.find_or_add(block);
+        self.counts[new_palette_index] += 1;
+        self.set_palette_index(index, new_palette_index);
+
+        old_block
+    }
+
+    pub fn get(&self, pos: &IVec3) -> Option<&BlockData> {
+        let index = local_index(pos)?;
+        self.palette[self.palette_index(index)].as_ref()
+    }
+
+    /// The block gets its own palette entry, so that modifying it leaves other blocks untouched
+    pub fn get_mut(&mut self, pos: &IVec3) -> Option<&mut BlockData> {
+        let index = local_index(pos)?;
+        let mut palette_index = self.palette_index(index);
+        self.palette[palette_index]?;
+
+        if self.counts[palette_index] > 1 {
+            let block = self.palette[palette_index];
+            self.counts[palette_index] -= 1;
+            palette_index = self.add_entry(block);
+            self.counts[palette_index] += 1;
+            self.set_palette_index(index, palette_index);
+        }
+
+        self.palette[palette_index].as_mut()
+    }
+
+    pub fn contains_key(&self, pos: &IVec3) -> bool {
+        self.get(pos).is_some()
+    }
+
+    /// Returns the block previously at this position
+    pub fn insert(&mut self, pos: IVec3, block: BlockData) -> Option<BlockData> {
+        let index = local_index(&pos)?;
+        self.replace(index, Some(block))
+    }
+
+    pub fn remove(&mut self, pos: &IVec3) -> Option<BlockData> {
+        let index = local_index(pos)?;
+        self.replace(index, None)
+    }
+
+    /// Number of non-air blocks
+    pub fn len(&self) -> usize {
+        self.palette
+            .iter()
+            .zip(self.counts.iter())
+            .filter(|(block, _)| block.is_some())
+            .map(|(_, count)| *count as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Heap memory used by the blocks, in bytes
+    pub fn heap_size(&self) -> usize {
+        self.palette.capacity() * std::mem::size_of::<Option<BlockData>>()
+            + self.counts.capacity() * std::mem::size_of::<u16>()
+            + self.data.capacity() * std::mem::size_of::<u64>()
+    }
+
+    /// Iterates over every non-air block, with its local position
+    pub fn iter(&self) -> impl Iterator<Item = (IVec3, &BlockData)> {
+        (0..BLOCKS_PER_CHUNK).filter_map(move |index| {
+            self.palette[self.palette_index(index)]
+                .as_ref()
+                .map(|block| (index_to_local_pos(index), block))
+        })
+    }
+}
+
+/// Serialized form of `PalettedBlocks`, block counts are rebuilt on load
+#[derive(Serialize)]
+struct PackedBlocksRef<'a> {
+    palette: &'a [Option<BlockData>],
+    bits_per_block: u8,
+    data: &'a [u64],
+}
+
+#[derive(Deserialize)]
+struct PackedBlocks {
+    palette: Vec<Option<BlockData>>,
+    bits_per_block: u8,
+    data: Vec<u64>,
+}
+
+impl Serialize for PalettedBlocks {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PackedBlocksRef {
+            palette: &self.palette,
+            bits_per_block: self.bits_per_block,
+            data: &self.data,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PalettedBlocks {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let packed = PackedBlocks::deserialize(deserializer)?;
+
+        if packed.palette.is_empty()
+            || packed.bits_per_block > MAX_BITS_PER_BLOCK
+            || bits_needed(packed.palette.len()) > packed.bits_per_block
+            || packed.data.len() != packed_len(packed.bits_per_block)
+        {
+            return Err(serde::de::Error::custom("invalid paletted chunk data"));
+        }
+
+        let mut blocks = Self {
+            counts: vec![0; packed.palette.len()],
+            palette: packed.palette,
+            bits_per_block: packed.bits_per_block,
+            data: packed.data,
+        };
+
+        for index in 0..BLOCKS_PER_CHUNK {
+            let palette_index = blocks.palette_index(index);
+            if palette_index >= blocks.palette.len() {
+                return Err(serde::de::Error::custom("palette index out of bounds"));
+            }
+            blocks.counts[palette_index] += 1;
+        }
+
+        Ok(blocks)
+    }
+}
+
+impl FromIterator<(IVec3, BlockData)> for PalettedBlocks {
+    fn from_iter<T: IntoIterator<Item = (IVec3, BlockData)>>(iter: T) -> Self {
+        let mut blocks = Self::default();
+        for (pos, block) in iter {
+            blocks.insert(pos, block);
+        }
+        blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{BlockDirection, BlockId};
+    use std::collections::HashMap;
+
+    fn block(id: BlockId) -> BlockData {
+        BlockData::new(id, BlockDirection::Front)
+    }
+
+    /// Distinct blocks, as many as needed to grow the palette
+    fn distinct_block(i: usize) -> BlockData {
+        BlockData {
+            id: if i.is_multiple_of(2) {
+                BlockId::Stone
+            } else {
+                BlockId::Dirt
+            },
+            direction: BlockDirection::Front,
+            breaking_progress: (i / 2) as u8,
+        }
+    }
+
+    fn contents(blocks: &PalettedBlocks) -> HashMap<IVec3, BlockData> {
+        blocks.iter().map(|(pos, block)| (pos, *block)).collect()
+    }
+
+    #[test]
+    fn get_insert_remove() {
+        let mut blocks = PalettedBlocks::default();
+        let pos = IVec3::new(1, 2, 3);
+
+        assert!(blocks.is_empty());
+        assert_eq!(blocks.get(&pos), None);
+
+        assert_eq!(blocks.insert(pos, block(BlockId::Stone)), None);
+        assert_eq!(blocks.get(&pos), Some(&block(BlockId::Stone)));
+        assert!(blocks.contains_key(&pos));
+        assert_eq!(blocks.len(), 1);
+
+        assert_eq!(
+            blocks.insert(pos, block(BlockId::Dirt)),
+            Some(block(BlockId::Stone))
+        );
+        assert_eq!(blocks.get(&pos), Some(&block(BlockId::Dirt)));
+        assert_eq!(blocks.len(), 1);
+
+        assert_eq!(blocks.remove(&pos), Some(block(BlockId::Dirt)));
+        assert_eq!(blocks.remove(&pos), None);
+        assert!(blocks.is_empty());
+    }
+
+    #[test]
+    fn positions_outside_of_the_chunk_are_ignored() {
+        let mut blocks = PalettedBlocks::default();
+
+        for pos in [IVec3::new(-1, 0, 0), IVec3::new(0, CHUNK_SIZE, 0)] {
+            assert_eq!(blocks.insert(pos, block(BlockId::Stone)), None);
+            assert_eq!(blocks.get(&pos), None);
+            assert_eq!(blocks.get_mut(&pos), None);
+            assert_eq!(blocks.remove(&pos), None);
+        }
+        assert!(blocks.is_empty());
+    }
+
+    #[test]
+    fn iter_matches_inserted_blocks() {
+        let expected: HashMap<IVec3, BlockData> = (0..CHUNK_SIZE)
+            .map(|i| {
+                (
+                    IVec3::new(i, (i * 7) % CHUNK_SIZE, CHUNK_SIZE - 1 - i),
+                    distinct_block(i as usize),
+                )
+            })
+            .collect();
+
+        let blocks: PalettedBlocks = expected.clone().into_iter().collect();
+
+        assert_eq!(contents(&blocks), expected);
+        assert_eq!(blocks.len(), expected.len());
+    }
+
+    #[test]
+    fn get_mut_only_modifies_one_block() {
+        let mut blocks = PalettedBlocks::default();
+        let (a, b) = (IVec3::new(0, 0, 0), IVec3::new(5, 5, 5));
+        blocks.insert(a, block(BlockId::Stone));
+        blocks.insert(b, block(BlockId::Stone));
+
+        blocks.get_mut(&a).unwrap().breaking_progress = 3;
+
+        assert_eq!(blocks.get(&a).unwrap().breaking_progress, 3);
+        assert_eq!(blocks.get(&b), Some(&block(BlockId::Stone)));
+
+        // A block alone in its entry is modified in place
+        let palette_len = blocks.palette.len();
+        blocks.get_mut(&a).unwrap().breaking_progress = 4;
+        assert_eq!(blocks.palette.len(), palette_len);
+        assert_eq!(blocks.get(&a).unwrap().breaking_progress, 4);
+
+        assert_eq!(blocks.get_mut(&IVec3::new(1, 1, 1)), None);
+    }
+
+    #[test]
+    fn palette_grows_and_compacts() {
+        let mut blocks = PalettedBlocks::default();
+        let mut expected = HashMap::new();
+
+        // Enough distinct blocks to need 9 bits per block
+        for i in 0..300 {
+            let pos = index_to_local_pos(i * 13);
+            blocks.insert(pos, distinct_block(i));
+            expected.insert(pos, distinct_block(i));
+        }
+        assert_eq!(blocks.bits_per_block, 9);
+        assert_eq!(contents(&blocks), expected);
+
+        // Only two distinct blocks remain
+        for i in 2..300 {
+            let pos = index_to_local_pos(i * 13);
+            blocks.remove(&pos);
+            expected.remove(&pos);
+        }
+        blocks.compact();
+        assert_eq!(blocks.palette.len(), 3);
+        assert_eq!(blocks.bits_per_block, 2);
+        assert_eq!(contents(&blocks), expected);
+    }
+
+    #[test]
+    fn unused_entries_are_reused() {
+        let mut blocks = PalettedBlocks::default();
+        let pos = IVec3::new(2, 2, 2);
+
+        for i in 0..100 {
+            blocks.insert(pos, distinct_block(i));
+        }
+
+        assert_eq!(blocks.palette.len(), 2);
+        assert_eq!(blocks.get(&pos), Some(&distinct_block(99)));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let mut blocks = PalettedBlocks::default();
+        for i in 0..40 {
+            blocks.insert(index_to_local_pos(i * 97), distinct_block(i));
+        }
+
+        let bytes = bincode::serialize(&blocks).unwrap();
+        let decoded: PalettedBlocks = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(contents(&decoded), contents(&blocks));
+        assert_eq!(decoded.counts, blocks.counts);
+    }
+
+    #[test]
+    fn empty_chunk_round_trip() {
+        let bytes = bincode::serialize(&PalettedBlocks::default()).unwrap();
+        let decoded: PalettedBlocks = bincode::deserialize(&bytes).unwrap();
+        assert!(decoded.is_empty());
+    }
+
+    fn decode(palette: &[Option<BlockData>], bits_per_block: u8, data: &[u64]) -> bool {
+        let bytes = bincode::serialize(&PackedBlocksRef {
+            palette,
+            bits_per_block,
+            data,
+        })
+        .unwrap();
+        bincode::deserialize::<PalettedBlocks>(&bytes).is_ok()
+    }
+
+    #[test]
+    fn invalid_data_is_rejected() {
+        let stone = Some(block(BlockId::Stone));
+
+        assert!(decode(&[None, stone], 1, &vec![0; packed_len(1)]));
+
+        // Empty palette
+        assert!(!decode(&[], 0, &[]));
+        // More bits than any chunk needs
+        assert!(!decode(
+            &[None],
+            MAX_BITS_PER_BLOCK + 1,
+            &vec![0; packed_len(MAX_BITS_PER_BLOCK + 1)]
+        ));
+        // Too few bits for the palette
+        assert!(!decode(&[None, stone], 0, &[]));
+        // Wrong data length
+        assert!(!decode(&[None, stone], 1, &vec![0; packed_len(1) - 1]));
+        // Index past the end of the palette
+        let mut data = vec![0; packed_len(2)];
+        data[0] = 0b11;
+        assert!(!decode(&[None, stone, stone], 2, &data));
+    }
+}